@@ -0,0 +1,128 @@
+//! Compare refiltering from scratch with incremental narrowing while a
+//! filter is typed one key at a time, and measure a whole key press in the
+//! picker including drawing the results
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ctrlrs::app::App;
+use ctrlrs::config::Config;
+use ctrlrs::history::{HistoryEntry, HistoryManager};
+use ctrlrs::matcher::{Filter, MatchMode};
+use ctrlrs::search::Search;
+use ctrlrs::ui::ui::ui;
+use ratatui::backend::TestBackend;
+use ratatui::Terminal;
+
+/// Number of synthetic history entries, about the size of a large shared history
+const ENTRIES: usize = 500_000;
+
+/// The filter as it looks after each key press
+const TYPED: [&str; 8] = [
+    "k",
+    "ku",
+    "kub",
+    "kube",
+    "kubec",
+    "kubect",
+    "kubectl",
+    "kubectl g",
+];
+
+/// The synthetic commands, oldest first
+fn commands() -> impl Iterator<Item = String> {
+    let tools = [
+        "git", "cargo", "kubectl", "docker", "ls", "vim", "ssh", "make",
+    ];
+    let verbs = [
+        "status", "build", "get pods", "logs -f", "run", "push", "apply -f", "test",
+    ];
+    (0..ENTRIES).map(move |i| {
+        format!(
+            "{} {} target-{}",
+            tools[i % tools.len()],
+            verbs[i / 7 % verbs.len()],
+            i
+        )
+    })
+}
+
+fn history() -> HistoryManager {
+    let entries = commands()
+        .map(|command| HistoryEntry {
+            full_command: command.clone(),
+            original_line: command.clone(),
+            command,
+            ..Default::default()
+        })
+        .collect();
+    HistoryManager::from_entries(entries, Vec::new())
+}
+
+fn typing(c: &mut Criterion) {
+    let history = history();
+
+    for mode in [MatchMode::Substring, MatchMode::Fuzzy] {
+        let mut group = c.benchmark_group(format!("typing/{}", mode.name()));
+        group.sample_size(10);
+
+        group.bench_function("scratch", |b| {
+            b.iter(|| {
+                for typed in TYPED {
+                    black_box(history.filter_matches(&[Filter::new(typed)], mode).unwrap());
+                }
+            })
+        });
+
+        group.bench_function("incremental", |b| {
+            b.iter(|| {
+                let mut search = Search::new();
+                for typed in TYPED {
+                    search
+                        .update(&history, &[Filter::new(typed)], mode)
+                        .unwrap();
+                    black_box(search.results());
+                }
+            })
+        });
+
+        group.finish();
+    }
+}
+
+/// Type the filter into the picker, drawing a frame after every key
+fn key_presses(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("bash_history");
+    let text: String = commands().map(|command| command + "\n").collect();
+    std::fs::write(&path, text).unwrap();
+
+    let mut config =
+        Config::new(Some("bash".to_string()), vec![path.display().to_string()]).unwrap();
+    config.cache_dir = None;
+    let mut app = App::new(config).unwrap();
+    while app.is_loading() {
+        app.poll_history().unwrap();
+    }
+    app.update_filters().unwrap();
+    let mut terminal = Terminal::new(TestBackend::new(120, 40)).unwrap();
+
+    let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+    let clear = KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL);
+
+    let mut group = c.benchmark_group("key_presses");
+    group.sample_size(10);
+    group.bench_function("filter_and_draw", |b| {
+        b.iter(|| {
+            for c in TYPED[TYPED.len() - 1].chars() {
+                app.handle_key_event(key(KeyCode::Char(c))).unwrap();
+                terminal.draw(|f| ui(f, &mut app)).unwrap();
+            }
+            app.handle_key_event(clear).unwrap();
+            terminal.draw(|f| ui(f, &mut app)).unwrap();
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, typing, key_presses);
+criterion_main!(benches);
@@ -15,12 +15,12 @@ function enhanced_ctrl_r() {
         temp_file=$(mktemp)
         
         # Run ctrlrs with the output file option
-        "${ctrlrs_path}" -o "$temp_file" </dev/tty >/dev/tty 2>/dev/null
+        "${ctrlrs_path}" -z -o "$temp_file" </dev/tty >/dev/tty 2>/dev/null
         
         # Read the selected command from the temp file if it exists and has content
         if [ -f "$temp_file" ] && [ -s "$temp_file" ]; then
             local result
-            result=$(cat "$temp_file")
+            IFS= read -r -d '' result < "$temp_file"
             
             # Update the command line with the selected command
             READLINE_LINE="$result"
@@ -19,11 +19,11 @@ function fish_user_key_bindings
         # Debugging output
         echo "ctrlrs_path is: '$ctrlrs_path'" >/dev/tty
 
-        echo "Running: $ctrlrs_path -o $temp_file" >/dev/tty
-        $ctrlrs_path -o $temp_file
+        echo "Running: $ctrlrs_path -z -o $temp_file" >/dev/tty
+        $ctrlrs_path -z -o $temp_file
 
         if test -s "$temp_file"
-            set -l result (cat "$temp_file")
+            read -l -z result < "$temp_file"
             commandline -r -- $result
             commandline -f repaint
         end
@@ -14,12 +14,12 @@ function enhanced_ctrl_r() {
         temp_file=$(mktemp "/tmp/ctrlrs.XXXXXX") || return
 
         # Run ctrlrs with the output file option
-        "${ctrlrs_path}" -o "$temp_file"
+        "${ctrlrs_path}" -z -o "$temp_file"
 
         # Read the selected command from the temp file if it exists and has content
         if [ -f "$temp_file" ] && [ -s "$temp_file" ]; then
             local result
-            result=$(cat "$temp_file")
+            IFS= read -r -d '' result < "$temp_file"
 	    echo $result
 
             # Update the command line with the selected command
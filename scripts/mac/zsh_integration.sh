@@ -29,12 +29,12 @@ function enhanced_ctrl_r() {
     
     # Run ctrlrs with the output file option using TTY redirection
     # This is the fix for macOS ZSH Ctrl+R issues
-    <$TTY "${ctrlrs_path}" -o "$temp_file"
+    <$TTY "${ctrlrs_path}" -z -o "$temp_file"
     
     # Read the selected command from the temp file if it exists and has content
     if [ -f "$temp_file" ] && [ -s "$temp_file" ]; then
         local result
-        result=$(cat "$temp_file")
+        IFS= read -r -d '' result < "$temp_file"
         
         # Set the command buffer if a result was selected
         BUFFER="$result"
@@ -66,12 +66,12 @@ function c() {
     temp_file=$(mktemp "/tmp/ctrlrs.XXXXXX") || return
     
     # Run ctrlrs with the output file option
-    <$TTY "${ctrlrs_path}" -o "$temp_file"
+    <$TTY "${ctrlrs_path}" -z -o "$temp_file"
     
     # Read the selected command from the temp file if it exists and has content
     if [ -f "$temp_file" ] && [ -s "$temp_file" ]; then
         local result
-        result=$(cat "$temp_file")
+        IFS= read -r -d '' result < "$temp_file"
         
         # Print the command to the terminal
         print -z "$result"
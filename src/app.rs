@@ -1,30 +1,77 @@
 //! Application state and logic
 
-use crate::config::Config;
-use crate::history::{HistoryEntry, HistoryManager};
+use crate::config::{Config, TimestampMode};
+use crate::editor::LineEditor;
+use crate::error::Error;
+use crate::history::{FilteredEntry, HistoryEntry, HistoryManager};
+use crate::keymap::{Action, EditMode};
+use crate::matcher::{Filter, MatchMode};
+use crate::ranking::RankMode;
+use crate::search::Search;
+use crate::shell::HistorySource;
 use crate::Result;
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Rect;
+use ratatui::widgets::ListState;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::{Duration, Instant};
 
 /// Result type for app operations
 pub type AppResult<T> = crate::Result<T>;
 
+/// Longest time between two clicks on a result that accept it
+const DOUBLE_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Something on screen that reacts to mouse clicks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClickTarget {
+    /// The filter of a dimension
+    Dimension(usize),
+    /// A result, by its index in the filtered entries
+    Result(usize),
+}
+
 /// Application state
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct App {
     /// Application configuration
     config: Config,
     /// History manager
     history_manager: HistoryManager,
-    /// Filters for each dimension
-    filters: Vec<String>,
+    /// Batches of history still being read in the background
+    history_loader: Option<Receiver<Vec<HistoryEntry>>>,
+    /// Filters for each dimension added so far
+    filters: Vec<Filter>,
+    /// The cursor in each dimension's filter
+    editors: Vec<LineEditor>,
+    /// The filter new dimensions start with, holding the configured match options
+    default_filter: Filter,
     /// Current filter dimension (0-based index)
     current_dimension: usize,
-    /// Maximum number of dimensions
-    max_dimensions: usize,
-    /// Filtered history entries
-    filtered_entries: Vec<HistoryEntry>,
+    /// Whether keys edit the filter or run actions, for modal keymaps
+    edit_mode: EditMode,
+    /// Maximum number of dimensions, `None` for no limit
+    max_dimensions: Option<usize>,
+    /// How filters are matched against commands
+    match_mode: MatchMode,
+    /// Invalid pattern error as (dimension, message), shown inline
+    pattern_error: Option<(usize, String)>,
+    /// Incremental search holding the filtered entries
+    search: Search,
     /// Selected entry index
     selected_index: usize,
+    /// Scroll position of the results list, kept between frames
+    results_state: ListState,
+    /// Number of results that fit on screen, for paging
+    page_size: usize,
+    /// Whether the preview of the selected command is shown
+    show_preview: bool,
+    /// How the column of timestamps next to results is shown
+    timestamp_mode: TimestampMode,
+    /// Where filters and results were drawn in the last frame
+    click_targets: Vec<(Rect, ClickTarget)>,
+    /// The last click on a result, to detect double clicks
+    last_click: Option<(Instant, ClickTarget)>,
     /// Whether the application should exit
     should_quit: bool,
     /// Selected command to return
@@ -34,125 +81,430 @@ pub struct App {
 impl App {
     /// Create a new application instance
     pub fn new(config: Config) -> Result<Self> {
-        let history_manager = HistoryManager::new(&config)?;
-        
-        // Initialize with 5 empty filters
-        let mut filters = Vec::with_capacity(5);
-        for _ in 0..5 {
-            filters.push(String::new());
-        }
-        
+        // Read the history in the background so the UI can draw right away
+        let history_loader = HistoryManager::load_in_background(
+            &config.history_sources,
+            config.cache_dir.as_deref(),
+        )?;
+        let history_manager = HistoryManager::empty(config.history_sources.clone())
+            .with_exclusions(config.exclude.clone())
+            .with_dedup(config.dedup)
+            .with_rank_mode(config.rank_mode);
+
+        // Start with one empty filter using the configured match options,
+        // further dimensions are added as they are needed
+        let default_filter = Filter {
+            case: config.case_mode,
+            whole_word: config.whole_word,
+            ..Filter::default()
+        };
+        let max_dimensions = config.max_dimensions;
+        let show_preview = config.preview;
+        let timestamp_mode = config.timestamps;
+
         Ok(Self {
+            match_mode: config.match_mode,
             config,
             history_manager,
-            filters,
+            history_loader: Some(history_loader),
+            filters: vec![default_filter.clone()],
+            editors: vec![LineEditor::new()],
+            default_filter,
             current_dimension: 0, // 0-based index (first dimension is 0)
-            max_dimensions: 5,    // Maximum of 5 dimensions
-            filtered_entries: Vec::new(),
+            edit_mode: EditMode::Insert,
+            max_dimensions,
+            pattern_error: None,
+            search: Search::new(),
             selected_index: 0,
+            results_state: ListState::default(),
+            page_size: 1,
+            show_preview,
+            timestamp_mode,
+            click_targets: Vec::new(),
+            last_click: None,
             should_quit: false,
             selected_command: None,
         })
     }
 
+    /// Merge history read by the background loader since the last call
+    ///
+    /// Returns whether the history changed, in which case the filters have
+    /// been applied again.
+    pub fn poll_history(&mut self) -> AppResult<bool> {
+        let Some(loader) = &self.history_loader else {
+            return Ok(false);
+        };
+
+        let mut changed = false;
+        loop {
+            match loader.try_recv() {
+                Ok(batch) => {
+                    self.history_manager.extend(batch);
+                    changed = true;
+                }
+                Err(TryRecvError::Empty) => break,
+                // The whole file has been read
+                Err(TryRecvError::Disconnected) => {
+                    self.history_loader = None;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+
+        if changed {
+            self.search.invalidate();
+            self.update_filters()?;
+        }
+
+        Ok(changed)
+    }
+
     /// Update filters and filtered entries
     pub fn update_filters(&mut self) -> AppResult<()> {
-        // Apply all active filters
-        let active_filters: Vec<&str> = self.filters.iter()
-            .take(self.current_dimension + 1) // Only use filters up to current dimension
-            .map(|s| s.as_str())
-            .collect();
-        
-        self.filtered_entries = self.history_manager.filter_multiple(&active_filters)?;
-        
+        // Apply the filters up to the current dimension, and later ones that
+        // still hold text, as after clicking back to an earlier dimension
+        let last = self
+            .filters
+            .iter()
+            .rposition(|filter| !filter.is_empty())
+            .map_or(self.current_dimension, |last| {
+                last.max(self.current_dimension)
+            });
+        let active_filters = &self.filters[..=last];
+
+        match self
+            .search
+            .update(&self.history_manager, active_filters, self.match_mode)
+        {
+            Ok(()) => {
+                self.pattern_error = None;
+            }
+            // Keep the previous results while a pattern is being typed
+            Err(Error::InvalidPattern { dimension, message }) => {
+                self.pattern_error = Some((dimension, message));
+            }
+            Err(e) => return Err(e),
+        }
+
         // Reset selected index if it's out of bounds
-        if !self.filtered_entries.is_empty() && self.selected_index >= self.filtered_entries.len() {
-            self.selected_index = self.filtered_entries.len() - 1;
+        if !self.search.results().is_empty() && self.selected_index >= self.search.results().len() {
+            self.selected_index = self.search.results().len() - 1;
         }
-        
+
         Ok(())
     }
 
     /// Handle key events
     pub fn handle_key_event(&mut self, key_event: KeyEvent) -> AppResult<()> {
-        match key_event.code {
-            // Quit
-            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.should_quit = true;
+        let Some(action) = self.config.keymap.action(self.edit_mode, &key_event) else {
+            // Unbound printable keys are typed into the current filter
+            if let KeyCode::Char(c) = key_event.code {
+                if self.edit_mode == EditMode::Insert
+                    && !key_event
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT)
+                {
+                    self.edit(|editor, text| {
+                        editor.insert(text, c.encode_utf8(&mut [0; 4]));
+                        true
+                    })?;
+                }
             }
-            KeyCode::Esc => {
+            return Ok(());
+        };
+
+        match action {
+            Action::Quit => {
                 self.should_quit = true;
             }
-            
+
             // Navigation
-            KeyCode::Up => {
-                if !self.filtered_entries.is_empty() {
-                    self.selected_index = self.selected_index.saturating_sub(1);
-                }
+            Action::SelectPrevious => self.select(self.selected_index.saturating_sub(1)),
+            Action::SelectNext => self.select(self.selected_index + 1),
+            Action::PageUp => self.select(self.selected_index.saturating_sub(self.page_size)),
+            Action::PageDown => self.select(self.selected_index + self.page_size),
+            Action::SelectFirst => self.select(0),
+            Action::SelectLast => self.select(usize::MAX),
+
+            // Selection
+            Action::Accept => self.accept(),
+
+            Action::NextDimension => {
+                self.next_dimension();
             }
-            KeyCode::Down => {
-                if !self.filtered_entries.is_empty() {
-                    self.selected_index = (self.selected_index + 1).min(self.filtered_entries.len() - 1);
-                }
+
+            Action::ToggleFuzzy => {
+                self.match_mode = self.match_mode.toggle_fuzzy();
+                self.update_filters()?;
             }
-            
-            // Selection
-            KeyCode::Enter => {
-                if !self.filtered_entries.is_empty() {
-                    self.selected_command = Some(self.filtered_entries[self.selected_index].command.clone());
-                    self.should_quit = true;
-                }
+
+            // Toggle between recency and frecency order
+            Action::ToggleRank => {
+                let rank_mode = self.history_manager.rank_mode().toggle();
+                self.history_manager.set_rank_mode(rank_mode);
+                self.search.invalidate();
+                self.update_filters()?;
             }
-            
-            // Switch to next dimension
-            KeyCode::Char('r') if key_event.modifiers.contains(KeyModifiers::CONTROL) => {
-                // Only move to next dimension if current filter is not empty and we haven't reached max
-                if !self.filters[self.current_dimension].is_empty() && 
-                   self.current_dimension < self.max_dimensions - 1 {
-                    self.current_dimension += 1;
-                }
+
+            Action::ToggleRegex => {
+                let filter = &mut self.filters[self.current_dimension];
+                filter.regex = !filter.regex;
+                self.update_filters()?;
+            }
+
+            Action::ToggleExclude => {
+                let filter = &mut self.filters[self.current_dimension];
+                filter.negated = !filter.negated;
+                self.update_filters()?;
             }
-            
-            // Text input
-            KeyCode::Char(c) => {
-                self.filters[self.current_dimension].push(c);
+
+            Action::CycleCase => {
+                let filter = &mut self.filters[self.current_dimension];
+                filter.case = filter.case.next();
                 self.update_filters()?;
             }
-            
-            KeyCode::Backspace => {
-                if !self.filters[self.current_dimension].is_empty() {
-                    self.filters[self.current_dimension].pop();
-                    self.update_filters()?;
+
+            Action::ToggleWord => {
+                let filter = &mut self.filters[self.current_dimension];
+                filter.whole_word = !filter.whole_word;
+                self.update_filters()?;
+            }
+
+            Action::TogglePreview => {
+                self.show_preview = !self.show_preview;
+            }
+
+            Action::CycleTimestamps => {
+                self.timestamp_mode = self.timestamp_mode.next();
+            }
+
+            Action::NormalMode if self.config.keymap.is_modal() => {
+                self.edit_mode = EditMode::Normal;
+            }
+            Action::InsertMode => {
+                self.edit_mode = EditMode::Insert;
+            }
+
+            Action::Backspace => {
+                if !self.filters[self.current_dimension].text.is_empty() {
+                    self.edit(LineEditor::delete_backward)?;
                 } else if self.current_dimension > 0 {
                     // Go back to previous dimension if current filter is empty
                     self.current_dimension -= 1;
                 }
             }
-            
+
+            // Line editing
+            Action::DeleteChar => self.edit(LineEditor::delete_forward)?,
+            Action::DeleteWord => self.edit(LineEditor::delete_word_backward)?,
+            Action::DeleteWordForward => self.edit(LineEditor::delete_word_forward)?,
+            Action::DeleteToStart => self.edit(LineEditor::delete_to_start)?,
+            Action::DeleteToEnd => self.edit(LineEditor::delete_to_end)?,
+            Action::CursorLeft => self.move_cursor(LineEditor::move_left),
+            Action::CursorRight => self.move_cursor(LineEditor::move_right),
+            Action::WordLeft => self.move_cursor(LineEditor::move_word_left),
+            Action::WordRight => self.move_cursor(LineEditor::move_word_right),
+            Action::LineStart => self.move_cursor(|editor, _| editor.move_start()),
+            Action::LineEnd => self.move_cursor(LineEditor::move_end),
+
             _ => {}
         }
-        
+
+        Ok(())
+    }
+
+    /// Handle mouse events
+    ///
+    /// The wheel moves the selection, a click selects a result or makes a
+    /// dimension active and a double click accepts a result.
+    pub fn handle_mouse_event(&mut self, mouse_event: MouseEvent) -> AppResult<()> {
+        match mouse_event.kind {
+            MouseEventKind::ScrollUp => self.select(self.selected_index.saturating_sub(1)),
+            MouseEventKind::ScrollDown => self.select(self.selected_index + 1),
+            MouseEventKind::Down(MouseButton::Left) => {
+                let (column, row) = (mouse_event.column, mouse_event.row);
+                let clicked = |area: &Rect| {
+                    (area.left()..area.right()).contains(&column)
+                        && (area.top()..area.bottom()).contains(&row)
+                };
+                let Some(&(_, target)) = self.click_targets.iter().find(|(area, _)| clicked(area))
+                else {
+                    return Ok(());
+                };
+
+                let now = Instant::now();
+                let double_click = self.last_click.is_some_and(|(time, last)| {
+                    last == target && now.duration_since(time) <= DOUBLE_CLICK_INTERVAL
+                });
+                self.last_click = Some((now, target));
+
+                match target {
+                    ClickTarget::Dimension(dim) if dim != self.current_dimension => {
+                        self.current_dimension = dim;
+                        self.update_filters()?;
+                    }
+                    ClickTarget::Dimension(_) => {}
+                    ClickTarget::Result(index) => {
+                        self.select(index);
+                        if double_click {
+                            self.accept();
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Pick the selected result, if there is one, and quit
+    fn accept(&mut self) {
+        if let Some(result) = self.search.results().get(self.selected_index) {
+            self.selected_command = Some(result.entry.full_command.clone());
+            self.should_quit = true;
+        }
+    }
+
+    /// Select a result, clamped to the last one
+    fn select(&mut self, index: usize) {
+        self.selected_index = index.min(self.search.results().len().saturating_sub(1));
+    }
+
+    /// Handle text pasted into the terminal
+    ///
+    /// The whole paste is inserted at the cursor as one edit, with line
+    /// breaks and other control characters turned into spaces.
+    pub fn handle_paste(&mut self, pasted: &str) -> AppResult<()> {
+        let pasted: String = pasted
+            .trim_end_matches(['\r', '\n'])
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect();
+        if pasted.is_empty() {
+            return Ok(());
+        }
+
+        self.edit_mode = EditMode::Insert;
+        self.edit(|editor, text| {
+            editor.insert(text, &pasted);
+            true
+        })
+    }
+
+    /// Edit the current filter, applying the filters again if it changed
+    fn edit(&mut self, edit: impl FnOnce(&mut LineEditor, &mut String) -> bool) -> AppResult<()> {
+        let dim = self.current_dimension;
+        if edit(&mut self.editors[dim], &mut self.filters[dim].text) {
+            self.update_filters()?;
+        }
         Ok(())
     }
 
+    /// Move the cursor in the current filter
+    fn move_cursor(&mut self, motion: impl FnOnce(&mut LineEditor, &str)) {
+        let dim = self.current_dimension;
+        motion(&mut self.editors[dim], &self.filters[dim].text);
+    }
+
+    /// Switch to the next dimension, adding it if it is new
+    fn next_dimension(&mut self) {
+        if self.can_add_dimension() {
+            self.current_dimension += 1;
+            if self.current_dimension == self.filters.len() {
+                self.filters.push(self.default_filter.clone());
+                self.editors.push(LineEditor::new());
+            }
+        }
+    }
+
+    /// Check whether another dimension can follow the current one
+    ///
+    /// Only a non-empty filter can be followed, and never past the maximum.
+    pub fn can_add_dimension(&self) -> bool {
+        !self.filters[self.current_dimension].is_empty()
+            && self
+                .max_dimensions
+                .is_none_or(|max| self.current_dimension + 1 < max)
+    }
+
+    /// Get the application configuration
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
     /// Get the filter for a specific dimension
     pub fn filter(&self, dimension: usize) -> &str {
+        &self.filters[dimension].text
+    }
+
+    /// Get the cursor position in a dimension's filter, as a byte offset
+    pub fn cursor(&self, dimension: usize) -> usize {
+        self.editors[dimension].cursor()
+    }
+
+    /// Get the filter and its options for a specific dimension
+    pub fn dimension_filter(&self, dimension: usize) -> &Filter {
         &self.filters[dimension]
     }
 
+    /// Get the invalid pattern message for a dimension, if any
+    pub fn pattern_error(&self, dimension: usize) -> Option<&str> {
+        match &self.pattern_error {
+            Some((dim, message)) if *dim == dimension => Some(message),
+            _ => None,
+        }
+    }
+
+    /// Check whether history is still being read
+    pub fn is_loading(&self) -> bool {
+        self.history_loader.is_some()
+    }
+
     /// Get the current filter dimension (0-based)
     pub fn current_dimension(&self) -> usize {
         self.current_dimension
     }
-    
-    /// Get the maximum number of dimensions
-    pub fn max_dimensions(&self) -> usize {
+
+    /// Get the maximum number of dimensions, `None` if there is no limit
+    pub fn max_dimensions(&self) -> Option<usize> {
         self.max_dimensions
     }
 
+    /// Get the number of dimensions added so far
+    pub fn dimension_count(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Get whether keys edit the filter or run actions
+    pub fn edit_mode(&self) -> EditMode {
+        self.edit_mode
+    }
+
+    /// Get the current match mode
+    pub fn match_mode(&self) -> MatchMode {
+        self.match_mode
+    }
+
+    /// Get the history file an entry came from, if several are being searched
+    pub fn entry_source(&self, entry: &HistoryEntry) -> Option<&HistorySource> {
+        if self.history_manager.sources().len() > 1 {
+            self.history_manager.source(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Get how results are ordered
+    pub fn rank_mode(&self) -> RankMode {
+        self.history_manager.rank_mode()
+    }
+
     /// Get the filtered history entries
-    pub fn filtered_entries(&self) -> &[HistoryEntry] {
-        &self.filtered_entries
+    pub fn filtered_entries(&self) -> &[FilteredEntry] {
+        self.search.results()
     }
 
     /// Get the selected index
@@ -160,6 +512,36 @@ impl App {
         self.selected_index
     }
 
+    /// Get the scroll state of the results list
+    pub fn results_state(&self) -> &ListState {
+        &self.results_state
+    }
+
+    /// Get the scroll state of the results list, to render it
+    pub fn results_state_mut(&mut self) -> &mut ListState {
+        &mut self.results_state
+    }
+
+    /// Whether the preview of the selected command is shown
+    pub fn show_preview(&self) -> bool {
+        self.show_preview
+    }
+
+    /// How the column of timestamps next to results is shown
+    pub fn timestamp_mode(&self) -> TimestampMode {
+        self.timestamp_mode
+    }
+
+    /// Set where filters and results were drawn, for mouse clicks
+    pub fn set_click_targets(&mut self, click_targets: Vec<(Rect, ClickTarget)>) {
+        self.click_targets = click_targets;
+    }
+
+    /// Set how many results fit on screen, used by PageUp and PageDown
+    pub fn set_page_size(&mut self, page_size: usize) {
+        self.page_size = page_size;
+    }
+
     /// Check if the application should quit
     pub fn should_quit(&self) -> bool {
         self.should_quit
@@ -170,3 +552,135 @@ impl App {
         self.selected_command.as_deref()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// An app over a bash history with the given commands, oldest first
+    fn app(commands: &[&str]) -> (App, TempDir) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bash_history");
+        std::fs::write(&path, commands.join("\n") + "\n").unwrap();
+        let mut config =
+            Config::new(Some("bash".to_string()), vec![path.display().to_string()]).unwrap();
+        config.cache_dir = None;
+
+        let mut app = App::new(config).unwrap();
+        while app.is_loading() {
+            app.poll_history().unwrap();
+        }
+        app.update_filters().unwrap();
+        (app, dir)
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    fn click(app: &mut App, column: u16, row: u16) {
+        let down = mouse(MouseEventKind::Down(MouseButton::Left), column, row);
+        app.handle_mouse_event(down).unwrap();
+    }
+
+    fn commands(app: &App) -> Vec<&str> {
+        app.filtered_entries()
+            .iter()
+            .map(|f| f.entry.command.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_mouse_wheel_and_clicks_select_results() {
+        let (mut app, _dir) = app(&["make", "git pull", "git push"]);
+        app.set_click_targets(vec![
+            (Rect::new(1, 4, 20, 1), ClickTarget::Result(0)),
+            (Rect::new(1, 5, 20, 1), ClickTarget::Result(1)),
+            (Rect::new(1, 6, 20, 2), ClickTarget::Result(2)),
+        ]);
+
+        app.handle_mouse_event(mouse(MouseEventKind::ScrollDown, 0, 0))
+            .unwrap();
+        app.handle_mouse_event(mouse(MouseEventKind::ScrollDown, 0, 0))
+            .unwrap();
+        app.handle_mouse_event(mouse(MouseEventKind::ScrollDown, 0, 0))
+            .unwrap();
+        assert_eq!(app.selected_index(), 2);
+        app.handle_mouse_event(mouse(MouseEventKind::ScrollUp, 0, 0))
+            .unwrap();
+        assert_eq!(app.selected_index(), 1);
+
+        // Any row of a wrapped result selects it, clicks elsewhere do nothing
+        click(&mut app, 5, 7);
+        assert_eq!(app.selected_index(), 2);
+        click(&mut app, 30, 4);
+        assert_eq!(app.selected_index(), 2);
+        assert!(!app.should_quit());
+    }
+
+    #[test]
+    fn test_double_click_accepts_result() {
+        let (mut app, _dir) = app(&["make", "git pull", "git push"]);
+        app.set_click_targets(vec![
+            (Rect::new(1, 4, 20, 1), ClickTarget::Result(0)),
+            (Rect::new(1, 5, 20, 1), ClickTarget::Result(1)),
+        ]);
+
+        // Clicks on different results, or too far apart, are single clicks
+        click(&mut app, 2, 4);
+        click(&mut app, 2, 5);
+        assert!(!app.should_quit());
+        let earlier = Instant::now()
+            .checked_sub(DOUBLE_CLICK_INTERVAL * 2)
+            .unwrap();
+        app.last_click = Some((earlier, ClickTarget::Result(1)));
+        click(&mut app, 2, 5);
+        assert!(!app.should_quit());
+
+        click(&mut app, 3, 5);
+        assert!(app.should_quit());
+        assert_eq!(app.selected_command(), Some("git pull"));
+    }
+
+    #[test]
+    fn test_clicking_earlier_dimension_keeps_later_filters() {
+        let (mut app, _dir) = app(&["git pull", "git push", "make push"]);
+        app.handle_paste("git").unwrap();
+        app.next_dimension();
+        app.handle_paste("push").unwrap();
+        assert_eq!(commands(&app), vec!["git push"]);
+
+        app.set_click_targets(vec![
+            (Rect::new(1, 1, 20, 3), ClickTarget::Dimension(0)),
+            (Rect::new(1, 4, 20, 3), ClickTarget::Dimension(1)),
+        ]);
+        click(&mut app, 4, 2);
+        assert_eq!(app.current_dimension(), 0);
+        assert_eq!(commands(&app), vec!["git push"]);
+
+        // Emptying the earlier filter leaves the later one applied
+        app.handle_key_event(KeyEvent::new(KeyCode::Char('u'), KeyModifiers::CONTROL))
+            .unwrap();
+        assert_eq!(commands(&app), vec!["git push", "make push"]);
+    }
+
+    #[test]
+    fn test_toggle_exclude_turns_typed_exclusion_off() {
+        let (mut app, _dir) = app(&["git get", "ls", "kubectl get pods"]);
+        app.handle_paste("!get").unwrap();
+        assert_eq!(commands(&app), vec!["ls"]);
+
+        let toggle = KeyEvent::new(KeyCode::Char('n'), KeyModifiers::ALT);
+        app.handle_key_event(toggle).unwrap();
+        assert_eq!(commands(&app), vec!["git get", "kubectl get pods"]);
+
+        app.handle_key_event(toggle).unwrap();
+        assert_eq!(commands(&app), vec!["ls"]);
+    }
+}
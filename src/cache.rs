@@ -0,0 +1,250 @@
+//! On-disk cache of parsed history files
+//!
+//! Shells only ever append to their history files, so the entries parsed on
+//! one run stay valid on the next. The cache remembers how much of the file
+//! was parsed, and the bytes at the start and end of that part, so a later
+//! run only parses what was appended. A truncated or rewritten file no longer
+//! matches and is parsed again from the start.
+
+use crate::error::Error;
+use crate::history::HistoryEntry;
+use crate::shell::HistoryFormat;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tempfile::NamedTempFile;
+
+/// Bumped whenever the cache layout or parsing changes, so old caches are rebuilt
+const CACHE_VERSION: u32 = 3;
+
+/// Number of bytes kept from the start and the end of the parsed part of a
+/// history file to notice when it was rewritten
+const SAMPLE_LEN: u64 = 4096;
+
+/// The state of a history file when it is read
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileState {
+    /// File length in bytes
+    pub len: u64,
+    /// Last modification time, if the platform reports it
+    modified: Option<SystemTime>,
+    /// The first bytes of the file
+    head: Vec<u8>,
+    /// The last bytes of the file
+    tail: Vec<u8>,
+}
+
+impl FileState {
+    /// Read the state of an open history file
+    pub fn read(file: &mut File) -> io::Result<Self> {
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+
+        Ok(Self {
+            len,
+            modified: metadata.modified().ok(),
+            head: read_range(file, 0, len.min(SAMPLE_LEN))?,
+            tail: read_range(file, len.saturating_sub(SAMPLE_LEN), len)?,
+        })
+    }
+}
+
+/// Read the bytes in `start..end` of a file
+fn read_range(file: &mut File, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity((end - start) as usize);
+    file.seek(SeekFrom::Start(start))?;
+    file.by_ref().take(end - start).read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// What is stored on disk for one history file
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheFile {
+    /// `CACHE_VERSION` at the time of writing
+    version: u32,
+    /// The history file the entries were parsed from
+    history_file: PathBuf,
+    /// The format the entries were parsed with
+    format: HistoryFormat,
+    /// The history file as it was parsed
+    state: FileState,
+    /// The parsed entries, in file order
+    entries: Vec<HistoryEntry>,
+}
+
+/// Parsed entries of one history file, kept on disk between runs
+#[derive(Debug, Clone)]
+pub struct HistoryCache {
+    /// Where the cache is stored
+    path: PathBuf,
+    /// The history file being cached
+    history_file: PathBuf,
+    /// The format of the history file
+    format: HistoryFormat,
+}
+
+impl HistoryCache {
+    /// Create the cache for a history file, stored under `cache_dir`
+    pub fn new(cache_dir: &Path, history_file: &Path, format: HistoryFormat) -> Self {
+        // One cache file per history file, named after its escaped path
+        let name = history_file
+            .to_string_lossy()
+            .replace('%', "%25")
+            .replace('/', "%2F")
+            .replace('\\', "%5C");
+
+        Self {
+            path: cache_dir.join(format!("{}.bin", name)),
+            history_file: history_file.to_path_buf(),
+            format,
+        }
+    }
+
+    /// Load the cached entries that are still valid for `file`
+    ///
+    /// Returns the entries in file order, with the offset where parsing
+    /// should continue. A missing or stale cache gives no entries and offset 0.
+    pub fn load(&self, file: &mut File, state: &FileState) -> (Vec<HistoryEntry>, u64) {
+        let cached = match self.read() {
+            Ok(cached) => cached,
+            Err(e) => {
+                log::debug!("Not using history cache: {}", e);
+                return (Vec::new(), 0);
+            }
+        };
+
+        if cached.version != CACHE_VERSION
+            || cached.history_file != self.history_file
+            || cached.format != self.format
+        {
+            return (Vec::new(), 0);
+        }
+
+        if *state == cached.state {
+            return (cached.entries, state.len);
+        }
+
+        // Appending only adds bytes after a complete last line, and leaves
+        // everything before it as it was
+        let old = &cached.state;
+        let appended = state.len > old.len
+            && old.tail.last().is_none_or(|&last| last == b'\n')
+            && state.head.starts_with(&old.head)
+            && read_range(file, old.len - old.tail.len() as u64, old.len)
+                .is_ok_and(|tail| tail == old.tail);
+
+        if appended {
+            (cached.entries, old.len)
+        } else {
+            log::info!(
+                "History file {} was rewritten, rebuilding its cache",
+                self.history_file.display()
+            );
+            (Vec::new(), 0)
+        }
+    }
+
+    /// Store the entries parsed from the history file in `state`
+    pub fn store(&self, state: FileState, entries: Vec<HistoryEntry>) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(dir)?;
+
+        let cached = CacheFile {
+            version: CACHE_VERSION,
+            history_file: self.history_file.clone(),
+            format: self.format,
+            state,
+            entries,
+        };
+
+        // Write a temporary file and move it in place, so a concurrent run
+        // never reads a half written cache
+        let mut temp = NamedTempFile::new_in(dir)?;
+        bincode::serialize_into(BufWriter::new(temp.as_file_mut()), &cached)
+            .map_err(|e| Error::Cache(e.to_string()))?;
+        temp.persist(&self.path)
+            .map_err(|e| Error::Cache(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Read the cache file
+    fn read(&self) -> Result<CacheFile> {
+        let file = File::open(&self.path)?;
+        bincode::deserialize_from(BufReader::new(file)).map_err(|e| Error::Cache(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn entry(command: &str) -> HistoryEntry {
+        HistoryEntry {
+            command: command.to_string(),
+            full_command: command.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cache_detects_append_and_rewrite() {
+        let dir = tempdir().unwrap();
+        let history_file = dir.path().join(".bash_history");
+        fs::write(&history_file, "ls\ncd /tmp\n").unwrap();
+
+        let cache = HistoryCache::new(
+            &dir.path().join("cache"),
+            &history_file,
+            HistoryFormat::Plain,
+        );
+        let mut file = File::open(&history_file).unwrap();
+        let state = FileState::read(&mut file).unwrap();
+
+        // Nothing is cached yet
+        assert_eq!(cache.load(&mut file, &state).1, 0);
+        cache
+            .store(state.clone(), vec![entry("ls"), entry("cd /tmp")])
+            .unwrap();
+
+        // Unchanged file
+        let (entries, offset) = cache.load(&mut file, &state);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(offset, state.len);
+
+        // Appended lines are parsed from where the cache ends
+        OpenOptions::new()
+            .append(true)
+            .open(&history_file)
+            .unwrap()
+            .write_all(b"pwd\n")
+            .unwrap();
+        let mut file = File::open(&history_file).unwrap();
+        let appended = FileState::read(&mut file).unwrap();
+        let (entries, offset) = cache.load(&mut file, &appended);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(offset, state.len);
+
+        // A rewritten file is parsed from the start
+        fs::write(&history_file, "vim\ncd /tmp\npwd\n").unwrap();
+        let mut file = File::open(&history_file).unwrap();
+        let rewritten = FileState::read(&mut file).unwrap();
+        let (entries, offset) = cache.load(&mut file, &rewritten);
+        assert!(entries.is_empty());
+        assert_eq!(offset, 0);
+
+        // So is a truncated one
+        fs::write(&history_file, "ls\n").unwrap();
+        let mut file = File::open(&history_file).unwrap();
+        let truncated = FileState::read(&mut file).unwrap();
+        let (entries, offset) = cache.load(&mut file, &truncated);
+        assert!(entries.is_empty());
+        assert_eq!(offset, 0);
+    }
+}
@@ -1,41 +1,375 @@
 //! Configuration for the ctrl-r application
+//!
+//! Settings come from `$XDG_CONFIG_HOME/ctrlrs/config.toml` (by default
+//! `~/.config/ctrlrs/config.toml`), and command line flags override them.
 
 use crate::error::Error;
-use crate::shell::ShellType;
+use crate::history::DedupMode;
+use crate::keymap::{Action, EditMode, Keymap, KeymapPreset, Keys};
+use crate::matcher::{CaseMode, MatchMode};
+use crate::ranking::RankMode;
+use crate::shell::{HistorySource, ShellType};
+use crate::ui::theme::Theme;
 use crate::Result;
-use std::path::PathBuf;
+use dirs::home_dir;
+use regex::Regex;
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Number of filter dimensions when the configuration file does not say
+pub const DEFAULT_MAX_DIMENSIONS: usize = 5;
+
+/// Fewest rows an inline picker takes, enough for a filter, a result and the
+/// status line
+pub const MIN_INLINE_HEIGHT: u16 = 5;
 
 /// Configuration for the ctrlrs application
 #[derive(Debug, Clone)]
 pub struct Config {
     /// The type of shell
     pub shell_type: ShellType,
-    /// The path to the history file
-    pub history_file: PathBuf,
+    /// The history files to search, merged into one list
+    pub history_sources: Vec<HistorySource>,
+    /// Commands matching any of these patterns are left out of the history
+    pub exclude: Vec<Regex>,
+    /// The match mode the picker starts in
+    pub match_mode: MatchMode,
+    /// Default case handling for new dimensions
+    pub case_mode: CaseMode,
+    /// Whether new dimensions only match whole words
+    pub whole_word: bool,
+    /// How repeated commands are merged
+    pub dedup: DedupMode,
+    /// How results are ordered before filters rank them
+    pub rank_mode: RankMode,
+    /// How many filter dimensions can be added, `None` for no limit
+    pub max_dimensions: Option<usize>,
+    /// Where parsed history is cached between runs, `None` to disable caching
+    pub cache_dir: Option<PathBuf>,
+    /// Whether the picker captures the mouse
+    pub mouse: bool,
+    /// Height of the picker drawn below the prompt, `None` to take the whole screen
+    pub height: Option<Height>,
+    /// Whether the preview of the selected command starts shown
+    pub preview: bool,
+    /// How the column of timestamps next to results starts
+    pub timestamps: TimestampMode,
+    /// Colors of the picker
+    pub theme: Theme,
+    /// Key bindings
+    pub keymap: Keymap,
+}
+
+/// The contents of the configuration file
+///
+/// Every setting is optional and falls back to the built-in default.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Shell whose history is searched when no history files are given
+    pub shell: Option<String>,
+    /// History files or globs, as accepted by `--history-file`
+    pub history_files: Vec<String>,
+    /// Regexes of commands to leave out of the history
+    #[serde(deserialize_with = "patterns")]
+    pub exclude: Vec<Regex>,
+    /// How many filter dimensions can be added, 0 for no limit
+    pub max_dimensions: Option<usize>,
+    /// Whether parsed history is cached between runs
+    pub cache: Option<bool>,
+    /// Whether the picker captures the mouse, turned off to let the terminal
+    /// select text
+    pub mouse: Option<bool>,
+    /// Height of the picker drawn below the prompt, instead of taking the
+    /// whole screen
+    pub height: Option<Height>,
+    /// Whether the preview of the selected command starts shown
+    pub preview: Option<bool>,
+    /// How the column of timestamps next to results starts
+    pub timestamps: Option<TimestampMode>,
+    /// Matching defaults, the `[matching]` table
+    pub matching: MatchingConfig,
+    /// Colors, the `[theme]` table
+    pub theme: Theme,
+    /// The built-in key bindings to start from
+    pub keymap: Option<KeymapPreset>,
+    /// Keys bound to each action, the `[keys]` table
+    pub keys: BTreeMap<Action, Keys>,
+    /// Keys bound to each action in vi normal mode, the `[normal_keys]` table
+    pub normal_keys: BTreeMap<Action, Keys>,
+}
+
+/// The `[matching]` table of the configuration file
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MatchingConfig {
+    /// The match mode the picker starts in
+    pub mode: Option<MatchMode>,
+    /// Default case handling for new dimensions
+    pub case: Option<CaseMode>,
+    /// Whether new dimensions only match whole words
+    pub whole_word: Option<bool>,
+    /// How repeated commands are merged
+    pub dedup: Option<DedupMode>,
+    /// How results are ordered
+    pub rank: Option<RankMode>,
+}
+
+/// How the column of timestamps next to results is shown
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampMode {
+    /// No column
+    #[default]
+    None,
+    /// How long ago the command last ran, such as `3m` or `2d`
+    Relative,
+    /// The date the command last ran
+    Absolute,
+}
+
+impl TimestampMode {
+    /// Cycle to the next timestamp mode
+    pub fn next(self) -> Self {
+        match self {
+            Self::None => Self::Relative,
+            Self::Relative => Self::Absolute,
+            Self::Absolute => Self::None,
+        }
+    }
+
+    /// Get the timestamp mode from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "none" | "off" => Some(Self::None),
+            "relative" => Some(Self::Relative),
+            "absolute" | "date" => Some(Self::Absolute),
+            _ => None,
+        }
+    }
+}
+
+/// Height of an inline picker, in rows or as a share of the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Height {
+    /// A number of rows
+    Rows(u16),
+    /// A percentage of the terminal's rows
+    Percent(u16),
+}
+
+impl Height {
+    /// Rows taken in a terminal of `terminal_rows`, at least `MIN_INLINE_HEIGHT`
+    /// unless the terminal is shorter
+    pub fn rows(self, terminal_rows: u16) -> u16 {
+        let rows = match self {
+            Self::Rows(rows) => rows,
+            Self::Percent(percent) => (u32::from(terminal_rows) * u32::from(percent) / 100) as u16,
+        };
+        rows.max(MIN_INLINE_HEIGHT).min(terminal_rows)
+    }
+}
+
+impl FromStr for Height {
+    type Err = Error;
+
+    /// Parse a height such as `20` or `40%`
+    fn from_str(s: &str) -> Result<Self> {
+        let error = || Error::Other(format!("Invalid height: {}", s));
+        match s.trim().strip_suffix('%') {
+            Some(percent) => match percent.trim().parse() {
+                Ok(percent @ 1..=100) => Ok(Self::Percent(percent)),
+                _ => Err(error()),
+            },
+            None => match s.trim().parse() {
+                Ok(rows @ 1..) => Ok(Self::Rows(rows)),
+                _ => Err(error()),
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Height {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        struct HeightVisitor;
+
+        impl<'de> Visitor<'de> for HeightVisitor {
+            type Value = Height;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number of rows or a percentage such as \"40%\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, rows: i64) -> std::result::Result<Height, E> {
+                self.visit_str(&rows.to_string())
+            }
+
+            fn visit_u64<E: de::Error>(self, rows: u64) -> std::result::Result<Height, E> {
+                self.visit_str(&rows.to_string())
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> std::result::Result<Height, E> {
+                s.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(HeightVisitor)
+    }
+}
+
+impl ConfigFile {
+    /// The default location of the configuration file
+    ///
+    /// This is `$XDG_CONFIG_HOME/ctrlrs/config.toml`, or
+    /// `~/.config/ctrlrs/config.toml` when `XDG_CONFIG_HOME` is not set.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| home_dir().map(|home| home.join(".config")))?;
+        Some(config_dir.join("ctrlrs").join("config.toml"))
+    }
+
+    /// Load the configuration file at `path`, or at the default location
+    ///
+    /// A missing file at the default location gives the default
+    /// configuration, while a missing file that was asked for is an error.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (path, required) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::parse(&text, &path),
+            Err(e) if e.kind() == io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(Error::Config(format!("{}: {}", path.display(), e))),
+        }
+    }
+
+    /// Parse the contents of a configuration file read from `path`
+    ///
+    /// Errors name the file and point at the offending line.
+    pub fn parse(text: &str, path: &Path) -> Result<Self> {
+        toml::from_str(text).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Deserialize a list of regexes
+fn patterns<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(de::Error::custom))
+        .collect()
 }
 
 impl Config {
-    /// Create a new configuration
+    /// Create a new configuration from command line arguments alone
+    ///
+    /// If shell_type or history_files are not provided, they will be auto-detected.
+    /// Each history file may be a glob and may name its shell, see
+    /// `HistorySource::expand`.
+    pub fn new(shell_type: Option<String>, history_files: Vec<String>) -> Result<Self> {
+        Self::from_file(ConfigFile::default(), shell_type, history_files)
+    }
+
+    /// The directory parsed histories are cached in, unless caching is off
+    pub fn default_cache_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("ctrlrs"))
+    }
+
+    /// Create a configuration from a configuration file and command line arguments
     ///
-    /// If shell_type or history_file are not provided, they will be auto-detected.
-    pub fn new(shell_type: Option<String>, history_file: Option<String>) -> Result<Self> {
-        let shell_type = if let Some(shell) = shell_type {
-            ShellType::from_name(&shell).ok_or_else(|| {
+    /// The shell and history files given on the command line replace those in
+    /// the file.
+    pub fn from_file(
+        file: ConfigFile,
+        shell_type: Option<String>,
+        history_files: Vec<String>,
+    ) -> Result<Self> {
+        let given_shell = match shell_type.or(file.shell) {
+            Some(shell) => Some(ShellType::from_name(&shell).ok_or_else(|| {
                 Error::ShellDetection(format!("Unsupported shell type: {}", shell))
-            })?
-        } else {
-            ShellType::detect()?
+            })?),
+            None => None,
+        };
+        let shell_type = match given_shell {
+            Some(shell_type) => shell_type,
+            None => ShellType::detect()?,
         };
 
-        let history_file = if let Some(path) = history_file {
-            PathBuf::from(path)
+        let history_files = if history_files.is_empty() {
+            file.history_files
+        } else {
+            history_files
+        };
+        let history_sources = if history_files.is_empty() {
+            vec![HistorySource::new(
+                shell_type.default_history_path()?,
+                shell_type,
+            )]
         } else {
-            shell_type.default_history_path()?
+            let mut sources = Vec::new();
+            for spec in &history_files {
+                sources.extend(HistorySource::expand(spec, given_shell, shell_type)?);
+            }
+            if sources.is_empty() {
+                return Err(Error::HistoryRead(format!(
+                    "No history files match {}",
+                    history_files.join(", ")
+                )));
+            }
+            sources
         };
 
+        let mut keymap = Keymap::preset(file.keymap.unwrap_or_default());
+        for (action, keys) in &file.keys {
+            keymap.rebind(EditMode::Insert, *action, &keys.0);
+        }
+        if keymap.is_modal() {
+            for (action, keys) in &file.normal_keys {
+                keymap.rebind(EditMode::Normal, *action, &keys.0);
+            }
+        } else if !file.normal_keys.is_empty() {
+            log::warn!("Ignoring [normal_keys], the keymap has no normal mode");
+        }
+
+        let matching = file.matching;
         Ok(Self {
             shell_type,
-            history_file,
+            history_sources,
+            exclude: file.exclude,
+            match_mode: matching.mode.unwrap_or_default(),
+            case_mode: matching.case.unwrap_or_default(),
+            whole_word: matching.whole_word.unwrap_or(false),
+            dedup: matching.dedup.unwrap_or_default(),
+            rank_mode: matching.rank.unwrap_or_default(),
+            max_dimensions: match file.max_dimensions {
+                Some(0) => None,
+                max => Some(max.unwrap_or(DEFAULT_MAX_DIMENSIONS)),
+            },
+            cache_dir: match file.cache {
+                Some(false) => None,
+                _ => Self::default_cache_dir(),
+            },
+            mouse: file.mouse.unwrap_or(true),
+            height: file.height,
+            preview: file.preview.unwrap_or(false),
+            timestamps: file.timestamps.unwrap_or_default(),
+            theme: file.theme,
+            keymap,
         })
     }
 }
@@ -43,17 +377,215 @@ impl Config {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::env;
 
     #[test]
     fn test_config_with_explicit_values() {
         let config = Config::new(
             Some("bash".to_string()),
-            Some("/tmp/test_history".to_string()),
+            vec!["/tmp/test_history".to_string()],
         )
         .unwrap();
 
         assert_eq!(config.shell_type, ShellType::Bash);
-        assert_eq!(config.history_file, PathBuf::from("/tmp/test_history"));
+        assert_eq!(
+            config.history_sources,
+            vec![HistorySource::new("/tmp/test_history", ShellType::Bash)]
+        );
+    }
+
+    #[test]
+    fn test_config_with_several_history_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("host-a"), ": 1700000000:0;ls\n").unwrap();
+        std::fs::write(dir.path().join("host-b"), "- cmd: ls\n  when: 1700000000\n").unwrap();
+        let bash = dir.path().join("work.hist");
+
+        let config = Config::new(
+            Some("bash".to_string()),
+            vec![
+                format!("{}/host-*", dir.path().display()),
+                format!("zsh:{}", bash.display()),
+            ],
+        )
+        .unwrap();
+
+        // The shell given on the command line beats guessing from the files
+        assert_eq!(
+            config.history_sources,
+            vec![
+                HistorySource::new(dir.path().join("host-a"), ShellType::Bash),
+                HistorySource::new(dir.path().join("host-b"), ShellType::Bash),
+                HistorySource::new(bash, ShellType::Zsh),
+            ]
+        );
+
+        let missing = format!("{}/nothing-*", dir.path().display());
+        assert!(Config::new(Some("bash".to_string()), vec![missing]).is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let text = r##"
+            shell = "zsh"
+            history_files = ["/tmp/test_history"]
+            exclude = ["^(ls|cd)( |$)"]
+            max_dimensions = 3
+            cache = false
+            mouse = false
+            height = "40%"
+            preview = true
+            timestamps = "relative"
+
+            [matching]
+            mode = "fuzzy"
+            case = "smart"
+            dedup = "whitespace"
+            rank = "frecency"
+
+            [theme]
+            selected = "#ff8800"
+            highlights = ["lightblue", "208"]
+
+            [keys]
+            next_dimension = ["ctrl-r", "tab"]
+            quit = "ctrl-g"
+        "##;
+        let file = ConfigFile::parse(text, Path::new("config.toml")).unwrap();
+        let config = Config::from_file(file, None, Vec::new()).unwrap();
+
+        assert_eq!(config.shell_type, ShellType::Zsh);
+        assert_eq!(
+            config.history_sources,
+            vec![HistorySource::new("/tmp/test_history", ShellType::Zsh)]
+        );
+        assert!(config.exclude[0].is_match("ls -la"));
+        assert!(!config.exclude[0].is_match("lsof"));
+        assert_eq!(config.max_dimensions, Some(3));
+        assert_eq!(config.cache_dir, None);
+        assert!(!config.mouse);
+        assert_eq!(config.height, Some(Height::Percent(40)));
+        assert!(config.preview);
+        assert_eq!(config.timestamps, TimestampMode::Relative);
+        assert_eq!(config.match_mode, MatchMode::Fuzzy);
+        assert_eq!(config.case_mode, CaseMode::Smart);
+        assert!(!config.whole_word);
+        assert_eq!(config.dedup, DedupMode::Whitespace);
+        assert_eq!(config.rank_mode, RankMode::Frecency);
+        assert_eq!(
+            config.theme.selected,
+            ratatui::style::Color::Rgb(0xff, 0x88, 0x00)
+        );
+        assert_eq!(
+            config.theme.highlights,
+            vec![
+                ratatui::style::Color::LightBlue,
+                ratatui::style::Color::Indexed(208)
+            ]
+        );
+        assert_eq!(
+            config
+                .keymap
+                .key_for(EditMode::Insert, Action::Quit)
+                .unwrap()
+                .to_string(),
+            "Ctrl+G"
+        );
+        assert_eq!(
+            config
+                .keymap
+                .key_for(EditMode::Insert, Action::NextDimension)
+                .unwrap()
+                .to_string(),
+            "Tab"
+        );
+
+        // Command line arguments win over the file
+        let file = ConfigFile::parse(text, Path::new("config.toml")).unwrap();
+        let config = Config::from_file(
+            file,
+            Some("bash".to_string()),
+            vec!["/tmp/other".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            config.history_sources,
+            vec![HistorySource::new("/tmp/other", ShellType::Bash)]
+        );
+    }
+
+    #[test]
+    fn test_config_file_vi_keymap() {
+        let text = "keymap = \"vi\"\n\n[normal_keys]\nquit = [\"q\", \"esc\"]\n";
+        let file = ConfigFile::parse(text, Path::new("config.toml")).unwrap();
+        let config = Config::from_file(file, Some("bash".to_string()), Vec::new()).unwrap();
+
+        assert!(config.keymap.is_modal());
+        assert_eq!(
+            config
+                .keymap
+                .key_for(EditMode::Insert, Action::NormalMode)
+                .unwrap()
+                .to_string(),
+            "Esc"
+        );
+        assert_eq!(
+            config
+                .keymap
+                .key_for(EditMode::Normal, Action::Quit)
+                .unwrap()
+                .to_string(),
+            "Esc"
+        );
+    }
+
+    #[test]
+    fn test_config_file_errors_point_at_line() {
+        let error = |text: &str| {
+            ConfigFile::parse(text, Path::new("/etc/ctrlrs.toml"))
+                .unwrap_err()
+                .to_string()
+        };
+
+        let message = error("shell = \"bash\"\n\n[matching]\nmode = \"fuzzzy\"\n");
+        assert!(message.contains("/etc/ctrlrs.toml"), "{}", message);
+        assert!(message.contains("line 4"), "{}", message);
+
+        assert!(error("max_dimensions = -1\n").contains("line 1"));
+        assert!(error("exclude = [\"(\"]\n").contains("line 1"));
+        assert!(error("[keys]\nquit = \"hyper-q\"\n").contains("line 2"));
+        assert!(error("[theme]\nmuted = \"greyish\"\n").contains("line 2"));
+        assert!(error("colour = true\n").contains("unknown field"));
+    }
+
+    #[test]
+    fn test_height() {
+        assert_eq!("12".parse::<Height>().unwrap(), Height::Rows(12));
+        assert_eq!("40%".parse::<Height>().unwrap(), Height::Percent(40));
+        assert!("0".parse::<Height>().is_err());
+        assert!("150%".parse::<Height>().is_err());
+        assert!("tall".parse::<Height>().is_err());
+
+        // Short heights still leave room for the picker, but never more than the terminal
+        assert_eq!(Height::Rows(12).rows(50), 12);
+        assert_eq!(Height::Percent(40).rows(50), 20);
+        assert_eq!(Height::Rows(2).rows(50), MIN_INLINE_HEIGHT);
+        assert_eq!(Height::Rows(80).rows(50), 50);
+        assert_eq!(Height::Percent(10).rows(3), 3);
+
+        let file = ConfigFile::parse("height = 15\n", Path::new("config.toml")).unwrap();
+        assert_eq!(file.height, Some(Height::Rows(15)));
+        assert!(ConfigFile::parse("height = -1\n", Path::new("config.toml")).is_err());
+    }
+
+    #[test]
+    fn test_missing_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert!(ConfigFile::load(Some(&path)).is_err());
+
+        fs::write(&path, "max_dimensions = 0\n").unwrap();
+        let file = ConfigFile::load(Some(&path)).unwrap();
+        let config = Config::from_file(file, Some("bash".to_string()), Vec::new()).unwrap();
+        assert_eq!(config.max_dimensions, None);
     }
 }
@@ -0,0 +1,223 @@
+//! Single-line editing of filter text
+
+/// A cursor in a line of text, with the edits made around it
+///
+/// The text itself lives in the filter being edited, so each edit takes it
+/// as an argument. The cursor is a byte offset that always sits on a
+/// character boundary.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEditor {
+    /// Byte offset of the cursor in the text
+    cursor: usize,
+}
+
+impl LineEditor {
+    /// Create an editor with the cursor at the start
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the byte offset of the cursor
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Insert text at the cursor and move past it
+    pub fn insert(&mut self, text: &mut String, inserted: &str) {
+        self.clamp(text);
+        text.insert_str(self.cursor, inserted);
+        self.cursor += inserted.len();
+    }
+
+    /// Move one character left
+    pub fn move_left(&mut self, text: &str) {
+        self.clamp(text);
+        self.cursor = Self::prev_char(text, self.cursor);
+    }
+
+    /// Move one character right
+    pub fn move_right(&mut self, text: &str) {
+        self.clamp(text);
+        self.cursor = Self::next_char(text, self.cursor);
+    }
+
+    /// Move to the start of the line
+    pub fn move_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    /// Move to the end of the line
+    pub fn move_end(&mut self, text: &str) {
+        self.cursor = text.len();
+    }
+
+    /// Move to the start of the current or previous word
+    pub fn move_word_left(&mut self, text: &str) {
+        self.clamp(text);
+        self.cursor = Self::word_start(text, self.cursor, char::is_alphanumeric);
+    }
+
+    /// Move to the end of the current or next word
+    pub fn move_word_right(&mut self, text: &str) {
+        self.clamp(text);
+        self.cursor = Self::word_end(text, self.cursor);
+    }
+
+    /// Delete the character before the cursor, returning whether anything changed
+    pub fn delete_backward(&mut self, text: &mut String) -> bool {
+        self.clamp(text);
+        let start = Self::prev_char(text, self.cursor);
+        self.delete(text, start, self.cursor)
+    }
+
+    /// Delete the character under the cursor, returning whether anything changed
+    pub fn delete_forward(&mut self, text: &mut String) -> bool {
+        self.clamp(text);
+        let end = Self::next_char(text, self.cursor);
+        self.delete(text, self.cursor, end)
+    }
+
+    /// Delete back to the previous whitespace, like Ctrl-W in a shell
+    pub fn delete_word_backward(&mut self, text: &mut String) -> bool {
+        self.clamp(text);
+        let start = Self::word_start(text, self.cursor, |c| !c.is_whitespace());
+        self.delete(text, start, self.cursor)
+    }
+
+    /// Delete to the end of the current or next word
+    pub fn delete_word_forward(&mut self, text: &mut String) -> bool {
+        self.clamp(text);
+        let end = Self::word_end(text, self.cursor);
+        self.delete(text, self.cursor, end)
+    }
+
+    /// Delete everything before the cursor
+    pub fn delete_to_start(&mut self, text: &mut String) -> bool {
+        self.clamp(text);
+        self.delete(text, 0, self.cursor)
+    }
+
+    /// Delete everything after the cursor
+    pub fn delete_to_end(&mut self, text: &mut String) -> bool {
+        self.clamp(text);
+        let end = text.len();
+        self.delete(text, self.cursor, end)
+    }
+
+    /// Delete `start..end` and leave the cursor at `start`
+    fn delete(&mut self, text: &mut String, start: usize, end: usize) -> bool {
+        if start == end {
+            return false;
+        }
+        text.replace_range(start..end, "");
+        self.cursor = start;
+        true
+    }
+
+    /// Keep the cursor inside text that may have changed without it
+    fn clamp(&mut self, text: &str) {
+        self.cursor = self.cursor.min(text.len());
+        while !text.is_char_boundary(self.cursor) {
+            self.cursor -= 1;
+        }
+    }
+
+    /// Offset of the character before `offset`
+    fn prev_char(text: &str, offset: usize) -> usize {
+        text[..offset]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i)
+    }
+
+    /// Offset of the character after `offset`
+    fn next_char(text: &str, offset: usize) -> usize {
+        text[offset..]
+            .chars()
+            .next()
+            .map_or(offset, |c| offset + c.len_utf8())
+    }
+
+    /// Start of the word before `offset`, skipping characters outside words first
+    fn word_start(text: &str, offset: usize, in_word: impl Fn(char) -> bool) -> usize {
+        let before = &text[..offset];
+        let trimmed = before.trim_end_matches(|c: char| !in_word(c));
+        trimmed.trim_end_matches(&in_word).len()
+    }
+
+    /// End of the word after `offset`, skipping characters outside words first
+    fn word_end(text: &str, offset: usize) -> usize {
+        let after = &text[offset..];
+        let skipped = after.trim_start_matches(|c: char| !c.is_alphanumeric());
+        let rest = skipped.trim_start_matches(char::is_alphanumeric);
+        text.len() - rest.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_move() {
+        let mut text = String::new();
+        let mut editor = LineEditor::new();
+
+        editor.insert(&mut text, "git push");
+        editor.move_word_left(&text);
+        assert_eq!(editor.cursor(), 4);
+        editor.insert(&mut text, "-C é ");
+        assert_eq!(text, "git -C é push");
+
+        editor.move_left(&text);
+        editor.move_left(&text);
+        assert_eq!(&text[editor.cursor()..], "é push");
+        editor.move_right(&text);
+        assert_eq!(&text[editor.cursor()..], " push");
+
+        editor.move_start();
+        editor.move_word_right(&text);
+        assert_eq!(editor.cursor(), 3);
+        editor.move_end(&text);
+        assert_eq!(editor.cursor(), text.len());
+    }
+
+    #[test]
+    fn test_deletes() {
+        let mut text = "kubectl get pods -n kube-system".to_string();
+        let mut editor = LineEditor::new();
+        editor.move_end(&text);
+
+        // Ctrl-W stops at whitespace, not punctuation
+        assert!(editor.delete_word_backward(&mut text));
+        assert_eq!(text, "kubectl get pods -n ");
+        assert!(editor.delete_word_backward(&mut text));
+        assert_eq!(text, "kubectl get pods ");
+
+        editor.move_start();
+        assert!(editor.delete_word_forward(&mut text));
+        assert_eq!(text, " get pods ");
+        assert!(editor.delete_forward(&mut text));
+        assert!(!editor.delete_backward(&mut text));
+        assert_eq!(text, "get pods ");
+
+        editor.move_word_right(&text);
+        assert!(editor.delete_to_end(&mut text));
+        assert_eq!(text, "get");
+        assert!(editor.delete_to_start(&mut text));
+        assert_eq!(text, "");
+        assert!(!editor.delete_to_start(&mut text));
+    }
+
+    #[test]
+    fn test_cursor_follows_shrunk_text() {
+        let mut text = "docker".to_string();
+        let mut editor = LineEditor::new();
+        editor.move_end(&text);
+
+        text.truncate(2);
+        assert!(editor.delete_backward(&mut text));
+        assert_eq!(text, "d");
+        assert_eq!(editor.cursor(), 1);
+    }
+}
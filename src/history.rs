@@ -1,282 +1,976 @@
-
-
 //! History file parsing and filtering
 
+use crate::cache::{FileState, HistoryCache};
 use crate::config::Config;
 use crate::error::Error;
-use crate::shell::{HistoryFormat, ShellType};
+use crate::matcher::{Filter, MatchMode};
+use crate::ranking::{Frecency, RankMode};
+use crate::search::Search;
+use crate::shell::{HistoryFormat, HistorySource, ShellType};
 use crate::Result;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::hash_map::{Entry, HashMap};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+
+/// Marker byte zsh writes before a metafied byte in its history file
+const ZSH_META: u8 = 0x83;
+
+/// Number of entries in the first batch sent by the background loader. Later
+/// batches double in size so merging them stays cheap overall
+const FIRST_BATCH: usize = 1024;
 
 /// A history entry
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
-    /// The command
+    /// The command, collapsed to a single line for display and matching
     pub command: String,
-    /// The timestamp (if available)
+    /// The exact command as it should be run, including newlines
+    pub full_command: String,
+    /// When the command was last run (if available)
     pub timestamp: Option<u64>,
-    /// The original line from the history file
+    /// When the command was first run (if available)
+    pub first_seen: Option<u64>,
+    /// How many runs were merged into this entry by deduplication
+    pub count: usize,
+    /// How long the command ran, in seconds (if available)
+    pub duration: Option<u64>,
+    /// The record as it appears in the history file, with every line of it
     pub original_line: String,
+    /// Paths referenced by the command (recorded by fish)
+    pub paths: Vec<String>,
+    /// Index of the history source the entry was read from, see
+    /// `HistoryManager::sources`
+    #[serde(skip)]
+    pub source: usize,
+}
+
+impl Default for HistoryEntry {
+    /// A single run of an empty command
+    fn default() -> Self {
+        Self {
+            command: String::new(),
+            full_command: String::new(),
+            timestamp: None,
+            first_seen: None,
+            count: 1,
+            duration: None,
+            original_line: String::new(),
+            paths: Vec::new(),
+            source: 0,
+        }
+    }
+}
+
+/// How repeated commands are merged
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DedupMode {
+    /// Keep every run as its own entry
+    None,
+    /// Merge runs of exactly the same command
+    #[default]
+    Exact,
+    /// Merge commands that only differ in whitespace
+    Whitespace,
+}
+
+impl DedupMode {
+    /// Get the dedup mode from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "exact" => Some(Self::Exact),
+            "whitespace" => Some(Self::Whitespace),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the dedup mode
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Exact => "exact",
+            Self::Whitespace => "whitespace",
+        }
+    }
+
+    /// The key under which runs of a command are merged
+    fn key<'a>(&self, command: &'a str) -> Cow<'a, str> {
+        match self {
+            Self::Whitespace => {
+                Cow::Owned(command.split_whitespace().collect::<Vec<_>>().join(" "))
+            }
+            _ => Cow::Borrowed(command),
+        }
+    }
+}
+
+/// A fish history record that is still being read
+#[derive(Debug, Default)]
+struct FishRecord {
+    /// The decoded command
+    command: String,
+    /// The `when:` timestamp
+    when: Option<u64>,
+    /// The decoded `paths:` list
+    paths: Vec<String>,
+    /// Whether the following `- ` lines belong to `paths:`
+    in_paths: bool,
+    /// The lines of the record as they appear in the file
+    raw: String,
+}
+
+/// A byte range of a command matched by one filter dimension
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Highlight {
+    /// The 0-based dimension that matched
+    pub dimension: usize,
+    /// The matched byte range in `HistoryEntry::command`
+    pub range: Range<usize>,
+}
+
+/// A history entry that passed the filters, with what each dimension matched
+#[derive(Debug, Clone)]
+pub struct FilteredEntry {
+    /// The history entry, shared with the history manager
+    pub entry: Arc<HistoryEntry>,
+    /// The matched ranges, in dimension order
+    pub highlights: Vec<Highlight>,
+}
+
+/// A filter on command duration, written as `dur>10s`, `dur<=2m`, etc.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct DurationFilter {
+    /// Shortest matching duration in seconds
+    min: u64,
+    /// Longest matching duration in seconds
+    max: u64,
+}
+
+impl DurationFilter {
+    /// Parse a duration filter, returning `None` for ordinary text filters
+    ///
+    /// A filter no duration can pass, such as `dur<0`, is an error rather
+    /// than text to search for.
+    pub(crate) fn parse(filter: &str) -> Option<std::result::Result<Self, String>> {
+        let rest = filter.trim().strip_prefix("dur")?;
+
+        let (op, value) = ["<=", ">=", "<", ">"]
+            .iter()
+            .find_map(|op| rest.strip_prefix(op).map(|value| (*op, value)))?;
+
+        let (digits, unit) = value.split_at(
+            value
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(value.len()),
+        );
+        let amount = digits.parse::<u64>().ok()?;
+        let seconds = match unit {
+            "" | "s" => amount,
+            "m" => amount.saturating_mul(60),
+            "h" => amount.saturating_mul(3600),
+            _ => return None,
+        };
+
+        Some(Ok(match op {
+            "<=" => Self {
+                min: 0,
+                max: seconds,
+            },
+            ">=" => Self {
+                min: seconds,
+                max: u64::MAX,
+            },
+            "<" => Self {
+                min: 0,
+                max: match seconds.checked_sub(1) {
+                    Some(max) => max,
+                    None => return Some(Err(format!("no duration is below {}", value))),
+                },
+            },
+            _ => Self {
+                min: seconds.saturating_add(1),
+                max: u64::MAX,
+            },
+        }))
+    }
+
+    /// Check whether a duration passes the filter (unknown durations never do)
+    pub(crate) fn matches(&self, duration: Option<u64>) -> bool {
+        duration.is_some_and(|d| d >= self.min && d <= self.max)
+    }
 }
 
 /// History manager
 #[derive(Debug, Clone)]
 pub struct HistoryManager {
-    /// The history entries
-    entries: Vec<HistoryEntry>,
-    /// The shell type
-    shell_type: ShellType,
+    /// The history entries, newest first
+    entries: Vec<Arc<HistoryEntry>>,
+    /// The entries in frecency order, when ranking by frecency
+    ranked: Option<Vec<Arc<HistoryEntry>>>,
+    /// The history files the entries were read from
+    sources: Vec<HistorySource>,
+    /// How repeated commands are merged
+    dedup: DedupMode,
+    /// How the entries are ordered
+    rank_mode: RankMode,
+    /// Commands matching any of these are left out
+    exclude: Vec<Regex>,
+    /// Scores entries by frecency, remembering which paths are in the
+    /// current directory across reranks
+    frecency: Frecency,
 }
 
 impl HistoryManager {
     /// Create a new history manager
     pub fn new(config: &Config) -> Result<Self> {
-        let entries = Self::read_history_file(&config.history_file, config.shell_type)?;
-        
-        Ok(Self {
-            entries,
-            shell_type: config.shell_type,
+        let mut manager = Self::empty(config.history_sources.clone())
+            .with_exclusions(config.exclude.clone())
+            .with_dedup(config.dedup)
+            .with_rank_mode(config.rank_mode);
+
+        for (index, source) in config.history_sources.iter().enumerate() {
+            let mut entries = Self::read_history_file(
+                &source.path,
+                source.shell_type,
+                config.cache_dir.as_deref(),
+            )?;
+            for entry in &mut entries {
+                entry.source = index;
+            }
+            manager.extend(entries);
+        }
+
+        Ok(manager)
+    }
+
+    /// Create a history manager without entries, to be filled by `extend`
+    pub fn empty(sources: Vec<HistorySource>) -> Self {
+        Self::from_entries(Vec::new(), sources)
+    }
+
+    /// Create a history manager from already parsed entries
+    pub fn from_entries(entries: Vec<HistoryEntry>, sources: Vec<HistorySource>) -> Self {
+        Self {
+            entries: entries.into_iter().map(Arc::new).collect(),
+            sources,
+            ranked: None,
+            dedup: DedupMode::default(),
+            rank_mode: RankMode::default(),
+            exclude: Vec::new(),
+            frecency: Frecency::new(),
+        }
+    }
+
+    /// Leave out commands matching any of the patterns, including current entries
+    pub fn with_exclusions(mut self, exclude: Vec<Regex>) -> Self {
+        self.exclude = exclude;
+        let exclude = &self.exclude;
+        self.entries
+            .retain(|entry| !Self::is_excluded(exclude, entry));
+        self.rerank();
+        self
+    }
+
+    /// Set how repeated commands are merged, applying it to the current entries
+    pub fn with_dedup(mut self, dedup: DedupMode) -> Self {
+        self.dedup = dedup;
+        Self::sort_and_dedup(&mut self.entries, dedup);
+        self.rerank();
+        self
+    }
+
+    /// Set how the entries are ordered
+    pub fn with_rank_mode(mut self, rank_mode: RankMode) -> Self {
+        self.set_rank_mode(rank_mode);
+        self
+    }
+
+    /// Change how the entries are ordered
+    pub fn set_rank_mode(&mut self, rank_mode: RankMode) {
+        self.rank_mode = rank_mode;
+        self.rerank();
+    }
+
+    /// Get how the entries are ordered
+    pub fn rank_mode(&self) -> RankMode {
+        self.rank_mode
+    }
+
+    /// Order the entries again after they or the rank mode changed
+    fn rerank(&mut self) {
+        self.ranked = match self.rank_mode {
+            RankMode::Recency => None,
+            RankMode::Frecency => {
+                let mut ranked = self.entries.clone();
+                self.frecency.rank(&mut ranked);
+                Some(ranked)
+            }
+        };
+    }
+
+    /// Open a history file, naming it in the error
+    fn open_history_file(path: &Path) -> Result<File> {
+        File::open(path).map_err(|e| {
+            Error::HistoryRead(format!(
+                "Failed to open history file {}: {}",
+                path.display(),
+                e
+            ))
         })
     }
 
-    /// Read history entries from a file
-    fn read_history_file(path: &Path, shell_type: ShellType) -> Result<Vec<HistoryEntry>> {
-        let file = File::open(path).map_err(|e| {
-            Error::HistoryRead(format!("Failed to open history file: {}", e))
-        })?;
-        
-        let format = shell_type.history_format();
+    /// Read history entries from a file in file order, using the cache under
+    /// `cache_dir` if given
+    fn read_history_file(
+        path: &Path,
+        shell_type: ShellType,
+        cache_dir: Option<&Path>,
+    ) -> Result<Vec<HistoryEntry>> {
+        let file = Self::open_history_file(path)?;
+
         let mut entries = Vec::new();
-        
-        // Process line by line for all formats
-        let reader = BufReader::new(file);
+        Self::read_cached(
+            file,
+            path,
+            shell_type.history_format(),
+            cache_dir,
+            &mut |entry| entries.push(entry),
+        );
+
+        Ok(entries)
+    }
+
+    /// Read history entries from an open file in file order, using and
+    /// updating the cache under `cache_dir` if given
+    ///
+    /// Cache problems are logged and the file is then parsed as usual.
+    fn read_cached(
+        mut file: File,
+        path: &Path,
+        format: HistoryFormat,
+        cache_dir: Option<&Path>,
+        emit: &mut impl FnMut(HistoryEntry),
+    ) {
+        let (cache, state) = match cache_dir.map(|dir| {
+            (
+                HistoryCache::new(dir, path, format),
+                FileState::read(&mut file),
+            )
+        }) {
+            Some((cache, Ok(state))) => (cache, state),
+            Some((_, Err(e))) => {
+                log::warn!("Not caching history: {}", e);
+                return Self::parse_entries(BufReader::new(file), format, emit);
+            }
+            None => return Self::parse_entries(BufReader::new(file), format, emit),
+        };
+
+        let (mut entries, offset) = cache.load(&mut file, &state);
+        for entry in &entries {
+            emit(entry.clone());
+        }
+        if offset == state.len {
+            return;
+        }
+
+        // Only parse what was appended, up to the length the cache will record
+        if let Err(e) = file.seek(SeekFrom::Start(offset)) {
+            log::warn!("Failed to read history file: {}", e);
+            return;
+        }
+        Self::parse_entries(
+            BufReader::new(file.take(state.len - offset)),
+            format,
+            &mut |entry| {
+                entries.push(entry.clone());
+                emit(entry);
+            },
+        );
+
+        if let Err(e) = cache.store(state, entries) {
+            log::warn!("Failed to write history cache: {}", e);
+        }
+    }
+
+    /// Read history files on a worker thread, one after the other
+    ///
+    /// The files are opened before returning so a missing file is reported
+    /// right away. Parsed entries, tagged with the index of their source,
+    /// arrive in batches of growing size to be passed to `extend`. The
+    /// channel disconnects once every file has been read.
+    pub fn load_in_background(
+        sources: &[HistorySource],
+        cache_dir: Option<&Path>,
+    ) -> Result<Receiver<Vec<HistoryEntry>>> {
+        let files = sources
+            .iter()
+            .map(|source| Ok((Self::open_history_file(&source.path)?, source.clone())))
+            .collect::<Result<Vec<_>>>()?;
+        let cache_dir: Option<PathBuf> = cache_dir.map(Path::to_path_buf);
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let mut batch = Vec::with_capacity(FIRST_BATCH);
+            let mut batch_size = FIRST_BATCH;
+            let mut stopped = false;
+
+            for (index, (file, source)) in files.into_iter().enumerate() {
+                let format = source.shell_type.history_format();
+                Self::read_cached(
+                    file,
+                    &source.path,
+                    format,
+                    cache_dir.as_deref(),
+                    &mut |mut entry| {
+                        if stopped {
+                            return;
+                        }
+                        entry.source = index;
+                        batch.push(entry);
+                        if batch.len() >= batch_size {
+                            batch_size *= 2;
+                            let full =
+                                std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+                            // Nobody is listening any more once the app has quit
+                            stopped = sender.send(full).is_err();
+                        }
+                    },
+                );
+            }
+
+            if !stopped && !batch.is_empty() {
+                let _ = sender.send(batch);
+            }
+        });
+
+        Ok(receiver)
+    }
+
+    /// Add entries read after the current ones, keeping the history sorted
+    /// and deduplicated as if everything had been parsed at once
+    pub fn extend(&mut self, batch: Vec<HistoryEntry>) {
+        let exclude = &self.exclude;
+        self.entries.extend(
+            batch
+                .into_iter()
+                .filter(|entry| !Self::is_excluded(exclude, entry))
+                .map(Arc::new),
+        );
+        Self::sort_and_dedup(&mut self.entries, self.dedup);
+        self.rerank();
+    }
+
+    /// Whether an entry matches one of the exclusion patterns
+    fn is_excluded(exclude: &[Regex], entry: &HistoryEntry) -> bool {
+        exclude
+            .iter()
+            .any(|pattern| pattern.is_match(&entry.full_command))
+    }
+
+    /// Parse history entries from a reader in file order, passing each one to `emit`
+    fn parse_entries<R: BufRead>(
+        reader: R,
+        format: HistoryFormat,
+        emit: &mut impl FnMut(HistoryEntry),
+    ) {
+        if format == HistoryFormat::Fish {
+            Self::parse_fish_history(reader, emit)
+        } else {
+            Self::parse_line_history(reader, format, emit)
+        }
+    }
+
+    /// Sort entries newest first and merge repeated commands
+    fn sort_and_dedup(entries: &mut Vec<Arc<HistoryEntry>>, dedup: DedupMode) {
+        // Sort entries by timestamp (if available) or just keep the order
+        entries.sort_by(|a, b| {
+            match (a.timestamp, b.timestamp) {
+                (Some(a_ts), Some(b_ts)) => b_ts.cmp(&a_ts), // Reverse order (newest first)
+                (Some(_), None) => std::cmp::Ordering::Less, // Entries with timestamps come first
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            }
+        });
+
+        if dedup == DedupMode::None {
+            return;
+        }
+
+        // Deduplicate entries, keeping only the first occurrence of each command
+        // (which will be the most recent due to the sorting above). Find the
+        // kept entry of each repeated one first, then merge them
+        let kept_at: Vec<Option<usize>> = {
+            let mut seen_commands = HashMap::new();
+            entries
+                .iter()
+                .enumerate()
+                .map(
+                    |(index, entry)| match seen_commands.entry(dedup.key(&entry.command)) {
+                        Entry::Occupied(kept) => Some(*kept.get()),
+                        Entry::Vacant(vacant) => {
+                            vacant.insert(index);
+                            None
+                        }
+                    },
+                )
+                .collect()
+        };
+
+        for (index, kept) in kept_at.iter().enumerate() {
+            if let Some(kept) = *kept {
+                let (count, first_seen) = (entries[index].count, entries[index].first_seen);
+                let kept = Arc::make_mut(&mut entries[kept]);
+                kept.count += count;
+                kept.first_seen = match (kept.first_seen, first_seen) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+
+        let mut kept_at = kept_at.into_iter();
+        entries.retain(|_| kept_at.next().flatten().is_none());
+    }
+
+    /// Parse line-oriented (plain, bash and zsh) history
+    fn parse_line_history<R: BufRead>(
+        reader: R,
+        format: HistoryFormat,
+        emit: &mut impl FnMut(HistoryEntry),
+    ) {
         let mut current_command = String::new();
+        let mut current_record = String::new();
         let mut current_timestamp: Option<u64> = None;
-        let timestamp_regex = Regex::new(r"^: (\d+):\d+;(.*)$").unwrap();
-        
-        // Read the file line by line, skipping lines with invalid UTF-8
-        for line_result in reader.lines() {
+        let mut current_duration: Option<u64> = None;
+        // Lines read since the last bash "#epoch" line, that line itself, and
+        // whether the file has shown multi-line records saved with
+        // `shopt -s lithist`
+        let mut record_lines: Vec<String> = Vec::new();
+        let mut timestamp_line = String::new();
+        let mut multiline_records = false;
+        let timestamp_regex = Regex::new(r"^: (\d+):(\d+);(.*)$").unwrap();
+
+        // Read the file line by line as bytes so zsh's metafied characters and
+        // stray invalid UTF-8 don't cost us the whole line
+        for line_result in reader.split(b'\n') {
             match line_result {
-                Ok(line) => {
+                Ok(bytes) => {
+                    let line = Self::decode_line(bytes, format);
                     if format == HistoryFormat::ZshExtended {
                         // Check if this is a new command (starts with timestamp)
                         if let Some(captures) = timestamp_regex.captures(&line) {
                             // If we have a previous command, add it as an entry
                             if !current_command.is_empty() {
-                                if let Some(entry) = Self::create_entry(&current_command, current_timestamp, format) {
-                                    entries.push(entry);
+                                if let Some(mut entry) =
+                                    Self::create_entry(&current_command, current_timestamp, format)
+                                {
+                                    entry.duration = current_duration;
+                                    entry.original_line = std::mem::take(&mut current_record);
+                                    emit(entry);
                                 }
                                 current_command.clear();
                             }
-                            
+                            current_record = line.clone();
+
                             // Extract timestamp and command
                             if let Some(ts_str) = captures.get(1) {
                                 if let Ok(ts) = ts_str.as_str().parse::<u64>() {
                                     current_timestamp = Some(ts);
                                 }
                             }
-                            
-                            if let Some(cmd) = captures.get(2) {
+
+                            current_duration = captures
+                                .get(2)
+                                .and_then(|elapsed| elapsed.as_str().parse::<u64>().ok());
+
+                            if let Some(cmd) = captures.get(3) {
                                 current_command = cmd.as_str().to_string();
                             }
                         } else {
-                            // This is a continuation of the previous command
+                            // This is a continuation of the previous command. Zsh writes
+                            // embedded newlines as a trailing backslash, so drop that one
                             if !current_command.is_empty() {
+                                if current_command.ends_with('\\') {
+                                    current_command.pop();
+                                }
                                 current_command.push('\n');
                                 current_command.push_str(&line);
+                                current_record.push('\n');
+                                current_record.push_str(&line);
                             }
                         }
+                    } else if format == HistoryFormat::BashTimestamped {
+                        if let Some(ts) = Self::parse_bash_timestamp(&line) {
+                            // A "#epoch" line ends the previous record. Several lines
+                            // before it can only be one command saved with lithist
+                            multiline_records |= record_lines.len() > 1;
+                            Self::emit_bash_record(
+                                &mut record_lines,
+                                &timestamp_line,
+                                current_timestamp,
+                                true,
+                                emit,
+                            );
+                            current_timestamp = Some(ts);
+                            timestamp_line = line;
+                        } else if current_timestamp.is_some() {
+                            record_lines.push(line);
+                        } else if let Some(entry) = Self::parse_history_line(&line, format) {
+                            // Lines written before HISTTIMEFORMAT was set have no timestamp
+                            emit(entry);
+                        }
                     } else {
                         // For other formats, each line is a separate command
                         if let Some(entry) = Self::parse_history_line(&line, format) {
-                            entries.push(entry);
+                            emit(entry);
                         }
                     }
-                },
+                }
                 Err(e) => {
                     // Log the error but continue processing
-                    log::warn!("Skipping line with invalid UTF-8: {}", e);
+                    log::warn!("Skipping unreadable history line: {}", e);
                     continue;
                 }
             }
         }
-        
+
+        // Lines after the last "#epoch" line run to the end of the file, so
+        // they only form one command if the file uses multi-line records.
+        // Otherwise they were written after HISTTIMEFORMAT was unset
+        Self::emit_bash_record(
+            &mut record_lines,
+            &timestamp_line,
+            current_timestamp,
+            multiline_records,
+            emit,
+        );
+
         // Add the last command if there is one
-        if format == HistoryFormat::ZshExtended && !current_command.is_empty() {
-            if let Some(entry) = Self::create_entry(&current_command, current_timestamp, format) {
-                entries.push(entry);
+        if !current_command.is_empty() {
+            if let Some(mut entry) = Self::create_entry(&current_command, current_timestamp, format)
+            {
+                entry.duration = current_duration;
+                entry.original_line = current_record;
+                emit(entry);
             }
         }
-        
-        // Sort entries by timestamp (if available) or just keep the order
-        entries.sort_by(|a, b| {
-            match (a.timestamp, b.timestamp) {
-                (Some(a_ts), Some(b_ts)) => b_ts.cmp(&a_ts), // Reverse order (newest first)
-                (Some(_), None) => std::cmp::Ordering::Less, // Entries with timestamps come first
-                (None, Some(_)) => std::cmp::Ordering::Greater,
-                (None, None) => std::cmp::Ordering::Equal,
+    }
+
+    /// Emit the lines read after a bash "#epoch" line
+    ///
+    /// When `joined` they are one multi-line command. Otherwise only the first
+    /// line belongs to the timestamp and each following line is a command of
+    /// its own.
+    fn emit_bash_record(
+        lines: &mut Vec<String>,
+        timestamp_line: &str,
+        timestamp: Option<u64>,
+        joined: bool,
+        emit: &mut impl FnMut(HistoryEntry),
+    ) {
+        let format = HistoryFormat::BashTimestamped;
+        let mut lines = std::mem::take(lines).into_iter();
+        let timestamped = |command: &str| {
+            let mut entry = Self::create_entry(command, timestamp, format)?;
+            entry.original_line = format!("{}\n{}", timestamp_line, command);
+            Some(entry)
+        };
+        if joined {
+            let command = lines.collect::<Vec<_>>().join("\n");
+            if let Some(entry) = timestamped(&command) {
+                emit(entry);
             }
-        });
-        
-        // Deduplicate entries, keeping only the first occurrence of each command
-        // (which will be the most recent due to the sorting above)
-        let mut unique_entries = Vec::new();
-        let mut seen_commands = std::collections::HashSet::new();
-        
-        for entry in entries {
-            if seen_commands.insert(entry.command.clone()) {
-                unique_entries.push(entry);
+            return;
+        }
+
+        if let Some(entry) = lines.next().and_then(|first| timestamped(&first)) {
+            emit(entry);
+        }
+        for line in lines {
+            if let Some(entry) = Self::parse_history_line(&line, format) {
+                emit(entry);
+            }
+        }
+    }
+
+    /// Decode one raw line of a history file into text
+    ///
+    /// Zsh lines are unmetafied first, unless they already are valid UTF-8:
+    /// metafied multi-byte characters never are, while a file that is only
+    /// read as zsh may hold text with the meta byte. Invalid UTF-8 is
+    /// replaced rather than dropping the line.
+    fn decode_line(mut bytes: Vec<u8>, format: HistoryFormat) -> String {
+        if bytes.last() == Some(&b'\r') {
+            bytes.pop();
+        }
+
+        if format == HistoryFormat::ZshExtended && std::str::from_utf8(&bytes).is_err() {
+            bytes = Self::unmetafy_zsh(&bytes);
+        }
+
+        match String::from_utf8(bytes) {
+            Ok(line) => line,
+            Err(e) => {
+                log::debug!("Recovering history line with invalid UTF-8: {}", e);
+                String::from_utf8_lossy(e.as_bytes()).into_owned()
+            }
+        }
+    }
+
+    /// Undo zsh's metafication, where a byte is stored as `ZSH_META` followed
+    /// by the byte XOR 32
+    fn unmetafy_zsh(bytes: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::with_capacity(bytes.len());
+        let mut iter = bytes.iter();
+
+        while let Some(&byte) = iter.next() {
+            if byte == ZSH_META {
+                if let Some(&next) = iter.next() {
+                    decoded.push(next ^ 32);
+                }
+            } else {
+                decoded.push(byte);
+            }
+        }
+
+        decoded
+    }
+
+    /// Parse a bash "#epoch" timestamp line written when HISTTIMEFORMAT is set
+    fn parse_bash_timestamp(line: &str) -> Option<u64> {
+        let digits = line.trim_end().strip_prefix('#')?;
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse::<u64>().ok()
+    }
+
+    /// Parse fish history records
+    ///
+    /// Fish stores each command as a YAML-like record:
+    ///
+    /// ```text
+    /// - cmd: cat notes.txt
+    ///   when: 1700000000
+    ///   paths:
+    ///     - notes.txt
+    /// ```
+    fn parse_fish_history<R: BufRead>(reader: R, emit: &mut impl FnMut(HistoryEntry)) {
+        let mut current: Option<FishRecord> = None;
+
+        for line_result in reader.split(b'\n') {
+            let line = match line_result {
+                Ok(bytes) => Self::decode_line(bytes, HistoryFormat::Fish),
+                Err(e) => {
+                    log::warn!("Skipping unreadable history line: {}", e);
+                    continue;
+                }
+            };
+
+            if let Some(cmd) = line.strip_prefix("- cmd:") {
+                // A new record starts, so the previous one is complete
+                if let Some(record) = current.take() {
+                    if let Some(entry) = Self::create_fish_entry(record) {
+                        emit(entry);
+                    }
+                }
+
+                current = Some(FishRecord {
+                    command: Self::unescape_fish(cmd.strip_prefix(' ').unwrap_or(cmd)),
+                    raw: line,
+                    ..FishRecord::default()
+                });
+            } else if let Some(record) = current.as_mut() {
+                record.raw.push('\n');
+                record.raw.push_str(&line);
+                if let Some(when) = line.strip_prefix("  when:") {
+                    record.when = when.trim().parse::<u64>().ok();
+                    record.in_paths = false;
+                } else if line.starts_with("  paths:") {
+                    record.in_paths = true;
+                } else if let Some(path) = line.strip_prefix("    - ") {
+                    if record.in_paths {
+                        record.paths.push(Self::unescape_fish(path));
+                    }
+                } else {
+                    // Unknown keys end the paths list
+                    record.in_paths = false;
+                }
             }
         }
-        
-        Ok(unique_entries)
+
+        if let Some(record) = current.take() {
+            if let Some(entry) = Self::create_fish_entry(record) {
+                emit(entry);
+            }
+        }
+    }
+
+    /// Create a history entry from a complete fish record
+    fn create_fish_entry(record: FishRecord) -> Option<HistoryEntry> {
+        let mut entry = Self::create_entry(&record.command, record.when, HistoryFormat::Fish)?;
+        entry.paths = record.paths;
+        entry.original_line = record.raw;
+        Some(entry)
     }
 
-    /// Create a history entry from a command and timestamp
-    fn create_entry(command: &str, timestamp: Option<u64>, _format: HistoryFormat) -> Option<HistoryEntry> {
+    /// Decode fish's history escaping (`\\` and `\n`)
+    fn unescape_fish(value: &str) -> String {
+        let mut decoded = String::with_capacity(value.len());
+        let mut chars = value.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                decoded.push(c);
+                continue;
+            }
+
+            match chars.next() {
+                Some('n') => decoded.push('\n'),
+                Some('\\') => decoded.push('\\'),
+                // Fish leaves any other backslash sequence untouched
+                Some(other) => {
+                    decoded.push('\\');
+                    decoded.push(other);
+                }
+                None => decoded.push('\\'),
+            }
+        }
+
+        decoded
+    }
+
+    /// Create a history entry from the exact command text and a timestamp
+    fn create_entry(
+        command: &str,
+        timestamp: Option<u64>,
+        _format: HistoryFormat,
+    ) -> Option<HistoryEntry> {
         if command.trim().is_empty() {
             return None;
         }
-        
-        // Convert multi-line commands to single line
-        let mut processed_command = command.replace('\n', " ");
-        // Handle both single and double backslashes (zsh stores backslashes as double backslashes)
-        processed_command = processed_command.replace(" \\\\", " ").replace(" \\", " ");
-        
+
         Some(HistoryEntry {
-            command: processed_command,
+            command: Self::display_command(command),
+            full_command: command.to_string(),
             timestamp,
+            first_seen: timestamp,
             original_line: command.to_string(),
+            ..Default::default()
         })
     }
 
+    /// Collapse a multi-line command into a single line for display and matching
+    fn display_command(command: &str) -> String {
+        // Drop "\" line continuations before joining the remaining lines
+        command.replace("\\\n", " ").replace('\n', " ")
+    }
+
     /// Parse a history line
     fn parse_history_line(line: &str, format: HistoryFormat) -> Option<HistoryEntry> {
         if line.trim().is_empty() {
             return None;
         }
-        
+
         match format {
-            HistoryFormat::Plain => {
+            HistoryFormat::BashTimestamped if Self::parse_bash_timestamp(line).is_some() => None,
+            HistoryFormat::Plain | HistoryFormat::BashTimestamped => {
                 // Simple format: just the command
-                // Convert multi-line commands to single line
-                // First, join all lines by replacing newlines with spaces
-                let mut command = line.replace('\n', " ");
-                // Then, remove any trailing backslashes that were used for line continuation
-                // Handle both single and double backslashes
-                command = command.replace(" \\\\", " ").replace(" \\", " ");
-                Some(HistoryEntry {
-                    command,
-                    timestamp: None,
-                    original_line: line.to_string(),
-                })
+                Self::create_entry(line, None, format)
             }
             HistoryFormat::ZshExtended => {
-                // Zsh format: ": timestamp:0;command"
-                let re = Regex::new(r"^: (\d+):\d+;(.*)$").ok()?;
+                // Zsh format: ": timestamp:elapsed;command"
+                let re = Regex::new(r"^: (\d+):(\d+);(.*)$").ok()?;
                 if let Some(captures) = re.captures(line) {
                     let timestamp = captures.get(1)?.as_str().parse::<u64>().ok()?;
-                    let mut command = captures.get(2)?.as_str().replace('\n', " ");
-                    // Handle both single and double backslashes (zsh stores backslashes as double backslashes)
-                    command = command.replace(" \\\\", " ").replace(" \\", " ");
-                    
-                    Some(HistoryEntry {
-                        command,
-                        timestamp: Some(timestamp),
-                        original_line: line.to_string(),
-                    })
+                    let mut entry =
+                        Self::create_entry(captures.get(3)?.as_str(), Some(timestamp), format)?;
+                    entry.duration = captures.get(2)?.as_str().parse::<u64>().ok();
+                    entry.original_line = line.to_string();
+                    Some(entry)
                 } else {
                     // Fall back to treating it as a plain command
-                    // Convert multi-line commands to single line
-                    let mut command = line.replace('\n', " ");
-                    command = command.replace(" \\\\", " ").replace(" \\", " ");
-                    Some(HistoryEntry {
-                        command,
-                        timestamp: None,
-                        original_line: line.to_string(),
-                    })
+                    Self::create_entry(line, None, format)
                 }
             }
             HistoryFormat::Fish => {
-                // Fish format is complex JSON-like, simplified here
-                // Just extract command part after "cmd:"
-                if let Some(cmd_start) = line.find("cmd:") {
-                    let cmd_part = &line[cmd_start + 4..];
-                    if let Some(cmd_end) = cmd_part.find("when:") {
-                        let mut command = cmd_part[..cmd_end].trim().trim_matches('"').replace('\n', " ");
-                        command = command.replace(" \\\\", " ").replace(" \\", " ");
-                        
-                        // Try to extract timestamp
-                        let timestamp = if let Some(when_start) = line.find("when:") {
-                            let when_part = &line[when_start + 5..];
-                            if let Some(when_end) = when_part.find(|c| c == ' ' || c == '\n') {
-                                when_part[..when_end].trim().parse::<u64>().ok()
-                            } else {
-                                None
-                            }
-                        } else {
-                            None
-                        };
-                        
-                        Some(HistoryEntry {
-                            command,
-                            timestamp,
-                            original_line: line.to_string(),
-                        })
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
+                // A single fish record line: "- cmd: command"
+                let cmd = line.strip_prefix("- cmd:")?;
+                Self::create_entry(
+                    &Self::unescape_fish(cmd.strip_prefix(' ').unwrap_or(cmd)),
+                    None,
+                    format,
+                )
             }
         }
     }
 
     /// Filter history entries with multiple filters
-    pub fn filter_multiple(&self, filters: &[&str]) -> Result<Vec<HistoryEntry>> {
-        // If no filters or first filter is empty, return all entries
-        if filters.is_empty() || filters[0].is_empty() {
-            return Ok(self.entries.clone());
-        }
-        
-        // Start with all entries
-        let mut filtered = self.entries.clone();
-        
-        // Apply each filter sequentially
-        for filter in filters {
-            if filter.is_empty() {
-                continue; // Skip empty filters
-            }
-            
-            let re = Regex::new(&format!("(?i){}", regex::escape(filter)))
-                .map_err(|e| Error::Other(format!("Invalid regex: {}", e)))?;
-            
-            filtered = filtered.into_iter()
-                .filter(|entry| re.is_match(&entry.command))
-                .collect();
-        }
-        
-        Ok(filtered)
-    }
-    
+    ///
+    /// Returns `Error::InvalidPattern` if a regex filter does not compile.
+    pub fn filter_multiple(
+        &self,
+        filters: &[Filter],
+        mode: MatchMode,
+    ) -> Result<Vec<HistoryEntry>> {
+        Ok(self
+            .filter_matches(filters, mode)?
+            .into_iter()
+            .map(|filtered| (*filtered.entry).clone())
+            .collect())
+    }
+
+    /// Filter history entries with multiple filters, keeping what each
+    /// dimension matched
+    ///
+    /// In fuzzy mode each dimension re-ranks the surviving entries by score,
+    /// keeping the previous order for equal scores.
+    ///
+    /// Returns `Error::InvalidPattern` if a regex filter does not compile.
+    pub fn filter_matches(
+        &self,
+        filters: &[Filter],
+        mode: MatchMode,
+    ) -> Result<Vec<FilteredEntry>> {
+        let mut search = Search::new();
+        search.update(self, filters, mode)?;
+
+        Ok(search.into_results())
+    }
+
     /// Filter history entries (legacy method for backward compatibility)
     pub fn filter(&self, first_filter: &str, second_filter: &str) -> Result<Vec<HistoryEntry>> {
-        let filters: Vec<&str> = if second_filter.is_empty() {
-            vec![first_filter]
+        let filters = if second_filter.is_empty() {
+            vec![Filter::new(first_filter)]
         } else {
-            vec![first_filter, second_filter]
+            vec![Filter::new(first_filter), Filter::new(second_filter)]
         };
-        
-        self.filter_multiple(&filters)
+
+        self.filter_multiple(&filters, MatchMode::Substring)
+    }
+
+    /// Get the history files the entries were read from
+    pub fn sources(&self) -> &[HistorySource] {
+        &self.sources
     }
 
-    /// Get all history entries
-    pub fn entries(&self) -> &[HistoryEntry] {
-        &self.entries
+    /// Get the history file an entry was read from
+    pub fn source(&self, entry: &HistoryEntry) -> Option<&HistorySource> {
+        self.sources.get(entry.source)
+    }
+
+    /// Get all history entries, in rank order
+    pub fn entries(&self) -> &[Arc<HistoryEntry>] {
+        self.ranked.as_deref().unwrap_or(&self.entries)
     }
 }
 
@@ -287,11 +981,29 @@ mod tests {
     use std::io::Write;
     use tempfile::tempdir;
 
+    /// Sort and deduplicate entries the way the history manager does
+    fn sorted(entries: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+        let mut manager = HistoryManager::empty(Vec::new());
+        manager.extend(entries);
+        manager
+            .entries()
+            .iter()
+            .map(|entry| (**entry).clone())
+            .collect()
+    }
+
+    /// Parse history the way the history manager reads it
+    fn parse_history(reader: &[u8], format: HistoryFormat) -> Vec<HistoryEntry> {
+        let mut entries = Vec::new();
+        HistoryManager::parse_entries(reader, format, &mut |entry| entries.push(entry));
+        sorted(entries)
+    }
+
     #[test]
     fn test_parse_bash_history() {
         let line = "ls -la";
         let entry = HistoryManager::parse_history_line(line, HistoryFormat::Plain).unwrap();
-        
+
         assert_eq!(entry.command, "ls -la");
         assert_eq!(entry.timestamp, None);
         assert_eq!(entry.original_line, "ls -la");
@@ -301,9 +1013,10 @@ mod tests {
     fn test_parse_zsh_history() {
         let line = ": 1738093190:0;ls -la";
         let entry = HistoryManager::parse_history_line(line, HistoryFormat::ZshExtended).unwrap();
-        
+
         assert_eq!(entry.command, "ls -la");
         assert_eq!(entry.timestamp, Some(1738093190));
+        assert_eq!(entry.duration, Some(0));
         assert_eq!(entry.original_line, ": 1738093190:0;ls -la");
     }
 
@@ -314,33 +1027,603 @@ mod tests {
                 command: "ls -la".to_string(),
                 timestamp: None,
                 original_line: "ls -la".to_string(),
+                ..Default::default()
             },
             HistoryEntry {
                 command: "cd /tmp".to_string(),
                 timestamp: None,
                 original_line: "cd /tmp".to_string(),
+                ..Default::default()
             },
             HistoryEntry {
                 command: "ls -l /tmp".to_string(),
                 timestamp: None,
                 original_line: "ls -l /tmp".to_string(),
+                ..Default::default()
             },
         ];
-        
-        let manager = HistoryManager {
-            entries,
-            shell_type: ShellType::Bash,
-        };
-        
+
+        let manager = HistoryManager::from_entries(entries, Vec::new());
+
         // Test first filter
         let filtered = manager.filter("ls", "").unwrap();
         assert_eq!(filtered.len(), 2);
         assert_eq!(filtered[0].command, "ls -la");
         assert_eq!(filtered[1].command, "ls -l /tmp");
-        
+
         // Test second filter
         let filtered = manager.filter("ls", "tmp").unwrap();
         assert_eq!(filtered.len(), 1);
         assert_eq!(filtered[0].command, "ls -l /tmp");
     }
+
+    #[test]
+    fn test_parse_fish_history() {
+        let sample = "\
+- cmd: cargo build --release
+  when: 1700000100
+- cmd: vim src/main.rs
+  when: 1700000200
+  paths:
+    - src/main.rs
+- cmd: git status
+  when: 1700000300
+";
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("fish_history");
+        File::create(&path)
+            .unwrap()
+            .write_all(sample.as_bytes())
+            .unwrap();
+
+        let entries =
+            sorted(HistoryManager::read_history_file(&path, ShellType::Fish, None).unwrap());
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].command, "git status");
+        assert_eq!(entries[0].timestamp, Some(1700000300));
+        assert_eq!(entries[1].command, "vim src/main.rs");
+        assert_eq!(entries[1].paths, vec!["src/main.rs".to_string()]);
+        assert_eq!(entries[2].command, "cargo build --release");
+        assert!(entries[2].paths.is_empty());
+    }
+
+    #[test]
+    fn test_parse_fish_history_escaping() {
+        let sample = r#"- cmd: for f in *.log\n    echo $f\nend
+  when: 1700000400
+  paths:
+    - C:\\logs
+- cmd: printf 'a\\tb'
+  when: 1700000500
+"#;
+        let entries = parse_history(sample.as_bytes(), HistoryFormat::Fish);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, r"printf 'a\tb'");
+        assert_eq!(entries[1].command, "for f in *.log     echo $f end");
+        assert_eq!(entries[1].full_command, "for f in *.log\n    echo $f\nend");
+        assert_eq!(entries[1].paths, vec![r"C:\logs".to_string()]);
+    }
+
+    #[test]
+    fn test_parse_fish_history_fixture() {
+        let sample = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/fish_history"
+        ));
+        let entries = parse_history(sample.as_bytes(), HistoryFormat::Fish);
+
+        let commands: Vec<&str> = entries.iter().map(|e| e.full_command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec![
+                "cd ~/projects/ctrlrs",
+                "vim src/history.rs Cargo.toml",
+                "grep -rn TODO src/ | wc -l",
+                "for f in *.log\n    gzip $f\nend",
+                r#"echo "first line\nsecond line""#,
+                "cargo test --workspace",
+            ]
+        );
+
+        // Repeated commands keep the newest `when:`
+        assert_eq!(entries[0].timestamp, Some(1712001400));
+        assert_eq!(entries[0].count, 2);
+        assert_eq!(
+            entries[1].paths,
+            vec!["src/history.rs".to_string(), "Cargo.toml".to_string()]
+        );
+        assert_eq!(entries[2].paths, vec!["src/".to_string()]);
+        assert_eq!(entries[3].command, "for f in *.log     gzip $f end");
+        assert!(entries[4].paths.is_empty());
+
+        // The original record keeps fish's escaping and every key
+        assert_eq!(
+            entries[2].original_line,
+            "- cmd: grep -rn TODO src/ | wc -l\n  when: 1712001260\n  paths:\n    - src/"
+        );
+        assert_eq!(
+            entries[3].original_line,
+            "- cmd: for f in *.log\\n    gzip $f\\nend\n  when: 1712001100"
+        );
+    }
+
+    #[test]
+    fn test_parse_bash_timestamped_history() {
+        let sample = "\
+echo from before HISTTIMEFORMAT
+#1700000100
+make test
+#1700000300
+git push
+#1700000200
+ls -la
+";
+        let entries = parse_history(sample.as_bytes(), HistoryFormat::BashTimestamped);
+
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec![
+                "git push",
+                "ls -la",
+                "make test",
+                "echo from before HISTTIMEFORMAT"
+            ]
+        );
+        assert_eq!(entries[0].timestamp, Some(1700000300));
+        assert_eq!(entries[3].timestamp, None);
+    }
+
+    #[test]
+    fn test_parse_bash_history_with_plain_sections() {
+        // HISTTIMEFORMAT was set for a while and then unset again
+        let sample = "\
+echo from before HISTTIMEFORMAT
+#1700000100
+make test
+#1700000200
+git push
+cargo build
+cargo test
+ls -la
+";
+        let entries = parse_history(sample.as_bytes(), HistoryFormat::BashTimestamped);
+
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec![
+                "git push",
+                "make test",
+                "echo from before HISTTIMEFORMAT",
+                "cargo build",
+                "cargo test",
+                "ls -la",
+            ]
+        );
+        assert_eq!(entries[0].timestamp, Some(1700000200));
+        assert_eq!(entries[3].timestamp, None);
+    }
+
+    #[test]
+    fn test_parse_bash_lithist_history() {
+        // With `shopt -s lithist` the lines of a command are kept as they are
+        let sample = "\
+#1700000100
+for f in *.txt; do
+  wc -l $f
+done
+#1700000200
+git status
+#1700000300
+if true; then
+  echo yes
+fi
+";
+        let entries = parse_history(sample.as_bytes(), HistoryFormat::BashTimestamped);
+
+        let commands: Vec<&str> = entries.iter().map(|e| e.full_command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec![
+                "if true; then\n  echo yes\nfi",
+                "git status",
+                "for f in *.txt; do\n  wc -l $f\ndone",
+            ]
+        );
+
+        // The original record starts with its "#epoch" line
+        assert_eq!(entries[1].original_line, "#1700000200\ngit status");
+    }
+
+    #[test]
+    fn test_parse_zsh_multiline_history() {
+        let sample = "\
+: 1700000100:0;for f in *.txt\\
+do\\
+  wc -l $f\\
+done
+: 1700000200:0;docker run \\\\
+  --rm alpine
+";
+        let entries = parse_history(sample.as_bytes(), HistoryFormat::ZshExtended);
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command, "docker run    --rm alpine");
+        assert_eq!(entries[0].full_command, "docker run \\\n  --rm alpine");
+        assert_eq!(
+            entries[0].original_line,
+            ": 1700000200:0;docker run \\\\\n  --rm alpine"
+        );
+        assert_eq!(entries[1].command, "for f in *.txt do   wc -l $f done");
+        assert_eq!(
+            entries[1].full_command,
+            "for f in *.txt\ndo\n  wc -l $f\ndone"
+        );
+    }
+
+    #[test]
+    fn test_parse_zsh_metafied_history() {
+        // "echo 😀" is F0 9F 98 80, and zsh metafies 0x9F and 0x98
+        let mut sample = b": 1700000100:0;echo \xF0\x83\xBF\x83\xB8\x80\n".to_vec();
+        sample.extend_from_slice(b": 1700000200:0;cd caf\xC3\xA9\n");
+        sample.extend_from_slice(b": 1700000300:0;ls \xFF\n");
+        // "ă" is C4 83, valid UTF-8 holding the meta byte, as in a file zsh did not write
+        sample.extend_from_slice(b": 1700000050:0;mv a\xC4\x83 b\n");
+
+        let entries = parse_history(sample.as_slice(), HistoryFormat::ZshExtended);
+
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec!["ls \u{FFFD}", "cd café", "echo 😀", "mv aă b"]
+        );
+    }
+
+    #[test]
+    fn test_filter_by_duration() {
+        let sample = "\
+: 1700000100:2;ls -la
+: 1700000200:95;cargo test
+: 1700000300:900;cargo build --release
+";
+        let manager = HistoryManager::from_entries(
+            parse_history(sample.as_bytes(), HistoryFormat::ZshExtended),
+            Vec::new(),
+        );
+        assert_eq!(manager.entries()[0].duration, Some(900));
+
+        let filtered = manager.filter("dur>10s", "").unwrap();
+        assert_eq!(filtered.len(), 2);
+
+        let filtered = manager.filter("cargo", "dur<=95").unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].command, "cargo test");
+
+        let filtered = manager.filter("dur>=15m", "").unwrap();
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].command, "cargo build --release");
+
+        // No command runs for less than no time, which is reported like a bad regex
+        match manager.filter("cargo", "dur<0") {
+            Err(Error::InvalidPattern { dimension, message }) => {
+                assert_eq!(dimension, 1);
+                assert_eq!(message, "no duration is below 0");
+            }
+            other => panic!(
+                "expected an invalid pattern, got {:?}",
+                other.map(|f| f.len())
+            ),
+        }
+    }
+
+    #[test]
+    fn test_filter_fuzzy() {
+        let sample = "\
+docker ps -a
+git checkout docs
+kubectl get pods
+docker ps
+";
+        let manager = HistoryManager::from_entries(
+            parse_history(sample.as_bytes(), HistoryFormat::Plain),
+            Vec::new(),
+        );
+
+        let filtered = manager
+            .filter_multiple(&[Filter::new("dkrps")], MatchMode::Fuzzy)
+            .unwrap();
+        let commands: Vec<&str> = filtered.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, vec!["docker ps -a", "docker ps"]);
+
+        let filtered = manager
+            .filter_multiple(&[Filter::new("dkrps")], MatchMode::Substring)
+            .unwrap();
+        assert!(filtered.is_empty());
+    }
+
+    #[test]
+    fn test_filter_invalid_regex() {
+        let manager = HistoryManager::from_entries(
+            parse_history("git push\n".as_bytes(), HistoryFormat::Plain),
+            Vec::new(),
+        );
+        let filters = [
+            Filter::new("git"),
+            Filter {
+                text: "(push".to_string(),
+                regex: true,
+                ..Filter::default()
+            },
+        ];
+
+        match manager.filter_multiple(&filters, MatchMode::Substring) {
+            Err(Error::InvalidPattern { dimension, message }) => {
+                assert_eq!(dimension, 1);
+                assert!(!message.is_empty());
+            }
+            other => panic!("expected an invalid pattern error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_filter_negated() {
+        let sample = "\
+kubectl get pods
+kubectl apply -f deploy.yaml
+kubectl get svc
+kubectl delete pod web-1
+git log
+";
+        let manager = HistoryManager::from_entries(
+            parse_history(sample.as_bytes(), HistoryFormat::Plain),
+            Vec::new(),
+        );
+
+        let filtered = manager.filter("kubectl", "!get").unwrap();
+        let commands: Vec<&str> = filtered.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec!["kubectl apply -f deploy.yaml", "kubectl delete pod web-1"]
+        );
+
+        // A lone "!" has nothing to exclude yet
+        let filtered = manager.filter("kubectl", "!").unwrap();
+        assert_eq!(filtered.len(), 4);
+    }
+
+    #[test]
+    fn test_filter_highlights() {
+        let manager = HistoryManager::from_entries(
+            parse_history("git push origin main\n".as_bytes(), HistoryFormat::Plain),
+            Vec::new(),
+        );
+
+        let filters = [
+            Filter::new("git"),
+            Filter::new("origin"),
+            Filter::new("!pull"),
+        ];
+        let filtered = manager
+            .filter_matches(&filters, MatchMode::Substring)
+            .unwrap();
+
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(
+            filtered[0].highlights,
+            vec![
+                Highlight {
+                    dimension: 0,
+                    range: 0..3
+                },
+                Highlight {
+                    dimension: 1,
+                    range: 9..15
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extend_matches_parse_history() {
+        let sample = "\
+: 1700000100:0;git status
+: 1700000300:0;cargo build
+: 1700000200:0;git status
+: 1700000400:0;ls
+: 1700000050:0;cargo build
+";
+        let expected = parse_history(sample.as_bytes(), HistoryFormat::ZshExtended);
+
+        let mut manager = HistoryManager::empty(Vec::new());
+        for line in sample.lines() {
+            manager.extend(parse_history(line.as_bytes(), HistoryFormat::ZshExtended));
+        }
+
+        let commands: Vec<(&str, Option<u64>)> = manager
+            .entries()
+            .iter()
+            .map(|e| (e.command.as_str(), e.timestamp))
+            .collect();
+        let expected: Vec<(&str, Option<u64>)> = expected
+            .iter()
+            .map(|e| (e.command.as_str(), e.timestamp))
+            .collect();
+        assert_eq!(commands, expected);
+        assert_eq!(commands[2], ("git status", Some(1700000200)));
+    }
+
+    #[test]
+    fn test_load_in_background() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".bash_history");
+        let mut file = File::create(&path).unwrap();
+        for i in 0..3000 {
+            writeln!(file, "#{}", 1700000000 + i).unwrap();
+            writeln!(file, "echo {}", i % 2500).unwrap();
+        }
+        drop(file);
+
+        let mut manager = HistoryManager::empty(Vec::new());
+        let loader =
+            HistoryManager::load_in_background(&[HistorySource::new(&path, ShellType::Bash)], None)
+                .unwrap();
+        let mut batches = 0;
+        for batch in loader {
+            manager.extend(batch);
+            batches += 1;
+        }
+
+        let expected =
+            sorted(HistoryManager::read_history_file(&path, ShellType::Bash, None).unwrap());
+        assert!(batches > 1);
+        assert_eq!(manager.entries().len(), 2500);
+        assert!(manager
+            .entries()
+            .iter()
+            .zip(&expected)
+            .all(|(a, b)| a.command == b.command));
+
+        assert!(HistoryManager::load_in_background(
+            &[HistorySource::new(
+                dir.path().join("missing"),
+                ShellType::Bash
+            )],
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_read_history_file_cached() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".zsh_history");
+        let cache_dir = dir.path().join("cache");
+        std::fs::write(
+            &path,
+            ": 1700000100:0;git status\n: 1700000200:0;cargo build\n",
+        )
+        .unwrap();
+
+        let commands = |entries: Vec<HistoryEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.command).collect()
+        };
+
+        // The first read fills the cache, the second one reads it back
+        let first = sorted(
+            HistoryManager::read_history_file(&path, ShellType::Zsh, Some(&cache_dir)).unwrap(),
+        );
+        let second = sorted(
+            HistoryManager::read_history_file(&path, ShellType::Zsh, Some(&cache_dir)).unwrap(),
+        );
+        assert_eq!(commands(first), vec!["cargo build", "git status"]);
+        assert_eq!(commands(second), vec!["cargo build", "git status"]);
+
+        // Appended entries are merged with the cached ones
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        writeln!(file, ": 1700000300:0;git status").unwrap();
+        drop(file);
+        let appended = sorted(
+            HistoryManager::read_history_file(&path, ShellType::Zsh, Some(&cache_dir)).unwrap(),
+        );
+        assert_eq!(appended[0].timestamp, Some(1700000300));
+        assert_eq!(commands(appended), vec!["git status", "cargo build"]);
+    }
+
+    #[test]
+    fn test_merge_history_sources() {
+        let dir = tempdir().unwrap();
+        let bash = dir.path().join(".bash_history");
+        let zsh = dir.path().join(".zsh_history");
+        std::fs::write(&bash, "#1700000100\ngit status\n#1700000300\ncargo test\n").unwrap();
+        std::fs::write(&zsh, ": 1700000200:0;ls\n: 1700000400:0;git status\n").unwrap();
+
+        let mut config = Config::new(
+            Some("bash".to_string()),
+            vec![bash.display().to_string(), format!("zsh:{}", zsh.display())],
+        )
+        .unwrap();
+        config.cache_dir = None;
+        let manager = HistoryManager::new(&config).unwrap();
+
+        let commands: Vec<(&str, usize)> = manager
+            .entries()
+            .iter()
+            .map(|e| (e.command.as_str(), e.source))
+            .collect();
+        assert_eq!(
+            commands,
+            vec![("git status", 1), ("cargo test", 0), ("ls", 1)]
+        );
+        assert_eq!(
+            manager.source(&manager.entries()[0]).unwrap().shell_type,
+            ShellType::Zsh
+        );
+    }
+
+    #[test]
+    fn test_dedup_modes() {
+        let sample = "\
+: 1700000100:0;git  status
+: 1700000200:0;ls
+: 1700000300:0;git status
+: 1700000400:0;git status
+";
+        let mut entries = Vec::new();
+        HistoryManager::parse_entries(
+            sample.as_bytes(),
+            HistoryFormat::ZshExtended,
+            &mut |entry| entries.push(entry),
+        );
+
+        let runs = |dedup: DedupMode| -> Vec<(String, usize, Option<u64>, Option<u64>)> {
+            HistoryManager::from_entries(entries.clone(), Vec::new())
+                .with_dedup(dedup)
+                .entries()
+                .iter()
+                .map(|e| (e.command.clone(), e.count, e.first_seen, e.timestamp))
+                .collect()
+        };
+
+        assert_eq!(runs(DedupMode::None).len(), 4);
+        assert_eq!(
+            runs(DedupMode::Exact),
+            vec![
+                (
+                    "git status".to_string(),
+                    2,
+                    Some(1700000300),
+                    Some(1700000400)
+                ),
+                ("ls".to_string(), 1, Some(1700000200), Some(1700000200)),
+                (
+                    "git  status".to_string(),
+                    1,
+                    Some(1700000100),
+                    Some(1700000100)
+                ),
+            ]
+        );
+        assert_eq!(
+            runs(DedupMode::Whitespace),
+            vec![
+                (
+                    "git status".to_string(),
+                    3,
+                    Some(1700000100),
+                    Some(1700000400)
+                ),
+                ("ls".to_string(), 1, Some(1700000200), Some(1700000200)),
+            ]
+        );
+        assert_eq!(
+            DedupMode::from_name("Whitespace"),
+            Some(DedupMode::Whitespace)
+        );
+    }
 }
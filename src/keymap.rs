@@ -0,0 +1,686 @@
+//! Key bindings
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::de::{self, Deserializer, SeqAccess, Visitor};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Something a key can be bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Leave without selecting a command
+    Quit,
+    /// Run the selected command
+    Accept,
+    /// Move the selection up
+    SelectPrevious,
+    /// Move the selection down
+    SelectNext,
+    /// Move the selection up by a screen
+    PageUp,
+    /// Move the selection down by a screen
+    PageDown,
+    /// Select the first result
+    SelectFirst,
+    /// Select the last result
+    SelectLast,
+    /// Add another filter dimension
+    NextDimension,
+    /// Delete the character before the cursor, or go back a dimension when
+    /// the filter is empty
+    Backspace,
+    /// Delete the character under the cursor
+    DeleteChar,
+    /// Delete back to the previous whitespace
+    DeleteWord,
+    /// Delete to the end of the word
+    DeleteWordForward,
+    /// Delete everything before the cursor
+    DeleteToStart,
+    /// Delete everything after the cursor
+    DeleteToEnd,
+    /// Move the cursor one character left
+    CursorLeft,
+    /// Move the cursor one character right
+    CursorRight,
+    /// Move the cursor to the previous word
+    WordLeft,
+    /// Move the cursor to the next word
+    WordRight,
+    /// Move the cursor to the start of the filter
+    LineStart,
+    /// Move the cursor to the end of the filter
+    LineEnd,
+    /// Switch between substring and fuzzy matching
+    ToggleFuzzy,
+    /// Switch between recency and frecency order
+    ToggleRank,
+    /// Toggle regex mode for the current dimension
+    ToggleRegex,
+    /// Toggle whether the current dimension excludes its matches
+    ToggleExclude,
+    /// Cycle case handling for the current dimension
+    CycleCase,
+    /// Toggle whole-word matching for the current dimension
+    ToggleWord,
+    /// Show or hide the preview of the selected command
+    TogglePreview,
+    /// Cycle the column of timestamps through hidden, relative and absolute
+    CycleTimestamps,
+    /// Switch to vi normal mode
+    NormalMode,
+    /// Switch to vi insert mode
+    InsertMode,
+}
+
+impl Action {
+    /// A short description for the status line
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Quit => "cancel",
+            Self::Accept => "select",
+            Self::SelectPrevious => "up",
+            Self::SelectNext => "down",
+            Self::PageUp => "page up",
+            Self::PageDown => "page down",
+            Self::SelectFirst => "first",
+            Self::SelectLast => "last",
+            Self::NextDimension => "add filter",
+            Self::Backspace => "delete",
+            Self::DeleteChar => "delete char",
+            Self::DeleteWord => "delete word",
+            Self::DeleteWordForward => "delete next word",
+            Self::DeleteToStart => "delete to start",
+            Self::DeleteToEnd => "delete to end",
+            Self::CursorLeft => "left",
+            Self::CursorRight => "right",
+            Self::WordLeft => "word left",
+            Self::WordRight => "word right",
+            Self::LineStart => "start",
+            Self::LineEnd => "end",
+            Self::ToggleFuzzy => "fuzzy",
+            Self::ToggleRank => "sort",
+            Self::ToggleRegex => "regex",
+            Self::ToggleExclude => "exclude",
+            Self::CycleCase => "case",
+            Self::ToggleWord => "word",
+            Self::TogglePreview => "preview",
+            Self::CycleTimestamps => "time",
+            Self::NormalMode => "normal mode",
+            Self::InsertMode => "insert mode",
+        }
+    }
+}
+
+/// A key together with the modifiers held down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyBinding {
+    /// The key
+    pub code: KeyCode,
+    /// Ctrl, Alt and Shift
+    pub modifiers: KeyModifiers,
+}
+
+impl KeyBinding {
+    /// Create a key binding
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// The binding a key event triggers
+    ///
+    /// Shift is part of the character for printable keys, so it is dropped
+    /// there to make `A` and `Shift+a` the same binding.
+    pub fn from_event(event: &KeyEvent) -> Self {
+        let mut modifiers =
+            event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        if matches!(event.code, KeyCode::Char(_)) {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self::new(event.code, modifiers)
+    }
+}
+
+/// Error for a key binding that cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKeyError(String);
+
+impl fmt::Display for ParseKeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid key binding \"{}\"", self.0)
+    }
+}
+
+impl std::error::Error for ParseKeyError {}
+
+impl FromStr for KeyBinding {
+    type Err = ParseKeyError;
+
+    /// Parse a binding such as `ctrl-r`, `alt+s`, `esc` or `pagedown`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || ParseKeyError(s.to_string());
+        let is_separator = |c: char| c == '-' || c == '+';
+
+        // The key itself may be a separator, as in `ctrl--`
+        let (prefix, key) = if s.len() == 1 {
+            ("", s)
+        } else if s.len() > 2
+            && s.ends_with(is_separator)
+            && s[..s.len() - 1].ends_with(is_separator)
+        {
+            (&s[..s.len() - 2], &s[s.len() - 1..])
+        } else {
+            s.rsplit_once(is_separator).unwrap_or(("", s))
+        };
+        if key.is_empty() || (prefix.is_empty() && key.len() < s.len()) {
+            return Err(error());
+        }
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in prefix.split(is_separator).filter(|_| !prefix.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" | "c" => KeyModifiers::CONTROL,
+                "alt" | "meta" | "m" => KeyModifiers::ALT,
+                "shift" | "s" => KeyModifiers::SHIFT,
+                _ => return Err(error()),
+            };
+        }
+
+        let code = match key.to_lowercase().as_str() {
+            "esc" | "escape" => KeyCode::Esc,
+            "enter" | "return" => KeyCode::Enter,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" | "bs" => KeyCode::Backspace,
+            "delete" | "del" => KeyCode::Delete,
+            "insert" | "ins" => KeyCode::Insert,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" | "pgup" => KeyCode::PageUp,
+            "pagedown" | "pgdn" => KeyCode::PageDown,
+            "space" => KeyCode::Char(' '),
+            name if name.len() > 1 && name.starts_with('f') => {
+                KeyCode::F(name[1..].parse().map_err(|_| error())?)
+            }
+            _ => {
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    // With Ctrl or Alt, letters are lower case unless Shift is given
+                    (Some(c), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                        modifiers.remove(KeyModifiers::SHIFT);
+                        KeyCode::Char(c.to_ascii_uppercase())
+                    }
+                    (Some(c), None) if !modifiers.is_empty() => {
+                        KeyCode::Char(c.to_ascii_lowercase())
+                    }
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(error()),
+                }
+            }
+        };
+
+        Ok(Self::new(code, modifiers))
+    }
+}
+
+impl fmt::Display for KeyBinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if !self.modifiers.is_empty() => {
+                write!(f, "{}", c.to_ascii_uppercase())
+            }
+            KeyCode::Char(c) => write!(f, "{}", c),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for KeyBinding {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Deserialize a single key binding or a list of them
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<KeyBinding>, D::Error> {
+    struct Bindings;
+
+    impl<'de> Visitor<'de> for Bindings {
+        type Value = Vec<KeyBinding>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a key binding or a list of key bindings")
+        }
+
+        fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+            Ok(vec![s.parse().map_err(E::custom)?])
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bindings = Vec::new();
+            while let Some(binding) = seq.next_element()? {
+                bindings.push(binding);
+            }
+            Ok(bindings)
+        }
+    }
+
+    deserializer.deserialize_any(Bindings)
+}
+
+/// Keys bound to an action in a configuration file
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct Keys(#[serde(deserialize_with = "one_or_many")] pub Vec<KeyBinding>);
+
+/// A built-in set of key bindings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeymapPreset {
+    /// Emacs-style bindings, without modes
+    #[default]
+    Emacs,
+    /// Vi-style bindings with a normal and an insert mode
+    Vi,
+}
+
+impl KeymapPreset {
+    /// Get the preset from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "emacs" | "default" => Some(Self::Emacs),
+            "vi" | "vim" => Some(Self::Vi),
+            _ => None,
+        }
+    }
+}
+
+/// The editing mode of a modal keymap
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EditMode {
+    /// Typing edits the current filter
+    #[default]
+    Insert,
+    /// Keys move around and run actions, like vi's normal mode
+    Normal,
+}
+
+impl EditMode {
+    /// Name shown in the status line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Insert => "INSERT",
+            Self::Normal => "NORMAL",
+        }
+    }
+}
+
+/// Maps keys to actions
+///
+/// Printable keys that are not bound insert text into the current filter
+/// in insert mode. Only modal keymaps, such as the vi preset, have a normal
+/// mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    /// The action of each key in insert mode
+    insert: HashMap<KeyBinding, Action>,
+    /// The action of each key in normal mode, for modal keymaps
+    normal: Option<HashMap<KeyBinding, Action>>,
+}
+
+impl Keymap {
+    /// A keymap without any bindings
+    pub fn empty() -> Self {
+        Self {
+            insert: HashMap::new(),
+            normal: None,
+        }
+    }
+
+    /// Create the keymap of a preset
+    pub fn preset(preset: KeymapPreset) -> Self {
+        match preset {
+            KeymapPreset::Emacs => Self::emacs(),
+            KeymapPreset::Vi => Self::vi(),
+        }
+    }
+
+    /// Emacs-style bindings
+    pub fn emacs() -> Self {
+        let mut keymap = Self::empty();
+        keymap.bind_common(EditMode::Insert);
+        keymap.bind_line_editing(EditMode::Insert);
+        keymap.bind(EditMode::Insert, key(KeyCode::Esc), Action::Quit);
+        keymap.bind(EditMode::Insert, ctrl('g'), Action::Quit);
+        keymap
+    }
+
+    /// Vi-style bindings, starting in insert mode
+    ///
+    /// Esc switches to normal mode, where `j` and `k` move the selection,
+    /// `h`, `l`, `w`, `b`, `0` and `$` move the cursor, `x` and `D` delete,
+    /// `g`, `G`, `Ctrl-U` and `Ctrl-D` jump through the results, `i`, `a` or
+    /// `/` go back to insert mode and `q` quits. Insert mode keeps the
+    /// readline editing keys.
+    pub fn vi() -> Self {
+        let mut keymap = Self::empty();
+        keymap.normal = Some(HashMap::new());
+
+        keymap.bind_common(EditMode::Insert);
+        keymap.bind_line_editing(EditMode::Insert);
+        keymap.bind(EditMode::Insert, key(KeyCode::Esc), Action::NormalMode);
+
+        keymap.bind_common(EditMode::Normal);
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('k')),
+            Action::SelectPrevious,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('j')),
+            Action::SelectNext,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('i')),
+            Action::InsertMode,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('a')),
+            Action::InsertMode,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('/')),
+            Action::InsertMode,
+        );
+        keymap.bind(EditMode::Normal, key(KeyCode::Char('q')), Action::Quit);
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('h')),
+            Action::CursorLeft,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('l')),
+            Action::CursorRight,
+        );
+        keymap.bind(EditMode::Normal, key(KeyCode::Char('b')), Action::WordLeft);
+        keymap.bind(EditMode::Normal, key(KeyCode::Char('w')), Action::WordRight);
+        keymap.bind(EditMode::Normal, key(KeyCode::Char('0')), Action::LineStart);
+        keymap.bind(EditMode::Normal, key(KeyCode::Char('$')), Action::LineEnd);
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('x')),
+            Action::DeleteChar,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('D')),
+            Action::DeleteToEnd,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('g')),
+            Action::SelectFirst,
+        );
+        keymap.bind(
+            EditMode::Normal,
+            key(KeyCode::Char('G')),
+            Action::SelectLast,
+        );
+        keymap.bind(EditMode::Normal, ctrl('u'), Action::PageUp);
+        keymap.bind(EditMode::Normal, ctrl('d'), Action::PageDown);
+        keymap
+    }
+
+    /// Readline-style bindings for editing the filter
+    fn bind_line_editing(&mut self, mode: EditMode) {
+        self.bind(mode, ctrl('a'), Action::LineStart);
+        self.bind(mode, ctrl('e'), Action::LineEnd);
+        self.bind(mode, ctrl('b'), Action::CursorLeft);
+        self.bind(mode, alt('b'), Action::WordLeft);
+        self.bind(mode, alt('f'), Action::WordRight);
+        self.bind(mode, ctrl('d'), Action::DeleteChar);
+        self.bind(mode, ctrl('w'), Action::DeleteWord);
+        self.bind(
+            mode,
+            KeyBinding::new(KeyCode::Backspace, KeyModifiers::ALT),
+            Action::DeleteWord,
+        );
+        self.bind(mode, alt('d'), Action::DeleteWordForward);
+        self.bind(mode, ctrl('u'), Action::DeleteToStart);
+        self.bind(mode, ctrl('k'), Action::DeleteToEnd);
+    }
+
+    /// Bindings shared by every preset and mode
+    fn bind_common(&mut self, mode: EditMode) {
+        self.bind(mode, ctrl('c'), Action::Quit);
+        self.bind(mode, key(KeyCode::Enter), Action::Accept);
+        self.bind(mode, key(KeyCode::Up), Action::SelectPrevious);
+        self.bind(mode, key(KeyCode::Down), Action::SelectNext);
+        self.bind(mode, ctrl('p'), Action::SelectPrevious);
+        self.bind(mode, ctrl('n'), Action::SelectNext);
+        self.bind(mode, key(KeyCode::PageUp), Action::PageUp);
+        self.bind(mode, key(KeyCode::PageDown), Action::PageDown);
+        self.bind(mode, key(KeyCode::Home), Action::SelectFirst);
+        self.bind(mode, key(KeyCode::End), Action::SelectLast);
+        self.bind(mode, ctrl('r'), Action::NextDimension);
+        self.bind(mode, key(KeyCode::Backspace), Action::Backspace);
+        self.bind(mode, key(KeyCode::Delete), Action::DeleteChar);
+        self.bind(mode, key(KeyCode::Left), Action::CursorLeft);
+        self.bind(mode, key(KeyCode::Right), Action::CursorRight);
+        self.bind(mode, ctrl('f'), Action::ToggleFuzzy);
+        self.bind(mode, alt('s'), Action::ToggleRank);
+        self.bind(mode, alt('r'), Action::ToggleRegex);
+        self.bind(mode, alt('n'), Action::ToggleExclude);
+        self.bind(mode, alt('c'), Action::CycleCase);
+        self.bind(mode, alt('w'), Action::ToggleWord);
+        self.bind(mode, alt('p'), Action::TogglePreview);
+        self.bind(mode, alt('t'), Action::CycleTimestamps);
+    }
+
+    /// Whether the keymap has a normal mode
+    pub fn is_modal(&self) -> bool {
+        self.normal.is_some()
+    }
+
+    /// The bindings of a mode, falling back to insert mode for keymaps without modes
+    fn bindings(&self, mode: EditMode) -> &HashMap<KeyBinding, Action> {
+        match (mode, &self.normal) {
+            (EditMode::Normal, Some(normal)) => normal,
+            _ => &self.insert,
+        }
+    }
+
+    /// Mutable bindings of a mode
+    fn bindings_mut(&mut self, mode: EditMode) -> &mut HashMap<KeyBinding, Action> {
+        match (mode, &mut self.normal) {
+            (EditMode::Normal, Some(normal)) => normal,
+            _ => &mut self.insert,
+        }
+    }
+
+    /// Bind a key to an action in a mode, replacing what it was bound to
+    pub fn bind(&mut self, mode: EditMode, key: KeyBinding, action: Action) {
+        self.bindings_mut(mode).insert(key, action);
+    }
+
+    /// Bind an action to exactly the given keys in a mode, replacing its current keys
+    pub fn rebind(&mut self, mode: EditMode, action: Action, keys: &[KeyBinding]) {
+        let bindings = self.bindings_mut(mode);
+        bindings.retain(|_, bound| *bound != action);
+        for key in keys {
+            bindings.insert(*key, action);
+        }
+    }
+
+    /// Get the action a key event triggers in a mode, if any
+    pub fn action(&self, mode: EditMode, event: &KeyEvent) -> Option<Action> {
+        self.bindings(mode)
+            .get(&KeyBinding::from_event(event))
+            .copied()
+    }
+
+    /// Get a key bound to an action in a mode, for display
+    pub fn key_for(&self, mode: EditMode, action: Action) -> Option<KeyBinding> {
+        // Prefer the binding with the fewest modifiers, then a stable order
+        self.bindings(mode)
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(key, _)| *key)
+            .min_by_key(|key| (key.modifiers.bits().count_ones(), key.to_string()))
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::emacs()
+    }
+}
+
+/// A key pressed with Ctrl
+fn ctrl(c: char) -> KeyBinding {
+    KeyBinding::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+}
+
+/// A key pressed with Alt
+fn alt(c: char) -> KeyBinding {
+    KeyBinding::new(KeyCode::Char(c), KeyModifiers::ALT)
+}
+
+/// A key pressed on its own
+fn key(code: KeyCode) -> KeyBinding {
+    KeyBinding::new(code, KeyModifiers::NONE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_binding() {
+        let parse = |s: &str| s.parse::<KeyBinding>().unwrap();
+
+        assert_eq!(
+            parse("ctrl-r"),
+            KeyBinding::new(KeyCode::Char('r'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            parse("Alt+S"),
+            KeyBinding::new(KeyCode::Char('s'), KeyModifiers::ALT)
+        );
+        assert_eq!(
+            parse("alt-shift-s"),
+            KeyBinding::new(KeyCode::Char('S'), KeyModifiers::ALT)
+        );
+        assert_eq!(
+            parse("pagedown"),
+            KeyBinding::new(KeyCode::PageDown, KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse("ctrl--"),
+            KeyBinding::new(KeyCode::Char('-'), KeyModifiers::CONTROL)
+        );
+        assert_eq!(
+            parse("-"),
+            KeyBinding::new(KeyCode::Char('-'), KeyModifiers::NONE)
+        );
+        assert_eq!(
+            parse("f5"),
+            KeyBinding::new(KeyCode::F(5), KeyModifiers::NONE)
+        );
+        assert!("hyper-x".parse::<KeyBinding>().is_err());
+        assert!("ctrl-".parse::<KeyBinding>().is_err());
+        assert!("-x".parse::<KeyBinding>().is_err());
+
+        assert_eq!(parse("ctrl-r").to_string(), "Ctrl+R");
+        assert_eq!(parse("esc").to_string(), "Esc");
+    }
+
+    #[test]
+    fn test_keymap_rebind() {
+        let mut keymap = Keymap::default();
+        let ctrl_r = KeyEvent::new(KeyCode::Char('r'), KeyModifiers::CONTROL);
+        let tab = KeyEvent::new(KeyCode::Tab, KeyModifiers::NONE);
+
+        assert_eq!(
+            keymap.action(EditMode::Insert, &ctrl_r),
+            Some(Action::NextDimension)
+        );
+
+        keymap.rebind(
+            EditMode::Insert,
+            Action::NextDimension,
+            &["tab".parse().unwrap()],
+        );
+        assert_eq!(keymap.action(EditMode::Insert, &ctrl_r), None);
+        assert_eq!(
+            keymap.action(EditMode::Insert, &tab),
+            Some(Action::NextDimension)
+        );
+        assert_eq!(
+            keymap
+                .key_for(EditMode::Insert, Action::NextDimension)
+                .unwrap()
+                .to_string(),
+            "Tab"
+        );
+    }
+
+    #[test]
+    fn test_vi_keymap_modes() {
+        let keymap = Keymap::preset(KeymapPreset::Vi);
+        let press = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+
+        assert!(keymap.is_modal());
+        // Letters are typed in insert mode and Esc does not quit
+        assert_eq!(keymap.action(EditMode::Insert, &press('j')), None);
+        assert_eq!(
+            keymap.action(EditMode::Insert, &esc),
+            Some(Action::NormalMode)
+        );
+        assert_eq!(
+            keymap.action(EditMode::Normal, &press('j')),
+            Some(Action::SelectNext)
+        );
+        assert_eq!(
+            keymap.action(EditMode::Normal, &press('k')),
+            Some(Action::SelectPrevious)
+        );
+        assert_eq!(
+            keymap.action(EditMode::Normal, &press('i')),
+            Some(Action::InsertMode)
+        );
+        assert_eq!(keymap.action(EditMode::Normal, &esc), None);
+
+        // Keymaps without modes ignore the mode
+        let emacs = Keymap::preset(KeymapPreset::Emacs);
+        assert!(!emacs.is_modal());
+        assert_eq!(emacs.action(EditMode::Normal, &esc), Some(Action::Quit));
+    }
+}
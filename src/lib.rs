@@ -4,8 +4,14 @@
 //! with an n-dimensional approach, allowing for multiple levels of nested filtering.
 
 pub mod app;
+pub mod cache;
 pub mod config;
+pub mod editor;
 pub mod history;
+pub mod keymap;
+pub mod matcher;
+pub mod ranking;
+pub mod search;
 pub mod shell;
 pub mod ui;
 
@@ -28,6 +34,23 @@ pub mod error {
         #[error("Failed to parse history: {0}")]
         HistoryParse(String),
 
+        /// Error when a filter pattern does not compile
+        #[error("Invalid pattern in dimension {}: {message}", dimension + 1)]
+        InvalidPattern {
+            /// The 0-based dimension holding the pattern
+            dimension: usize,
+            /// What is wrong with the pattern
+            message: String,
+        },
+
+        /// Error in the configuration file
+        #[error("Invalid configuration: {0}")]
+        Config(String),
+
+        /// Error when reading or writing the history cache
+        #[error("History cache error: {0}")]
+        Cache(String),
+
         /// Error with the terminal UI
         #[error("Terminal UI error: {0}")]
         Ui(String),
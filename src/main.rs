@@ -1,14 +1,17 @@
 use clap::Parser;
 use ctrlrs::app::App;
-use ctrlrs::config::Config;
+use ctrlrs::config::{Config, ConfigFile, TimestampMode};
+use ctrlrs::history::DedupMode;
+use ctrlrs::keymap::KeymapPreset;
+use ctrlrs::matcher::{CaseMode, MatchMode};
+use ctrlrs::ranking::RankMode;
 use ctrlrs::ui::ui::*;
 use ctrlrs::Result;
-use std::fs::File;
 use std::io::Write;
-use tempfile::NamedTempFile;
+use std::path::PathBuf;
 
-/// Enhanced Ctrl-R for shell history with n-dimensional search (up to 5 dimensions)
-/// 
+/// Enhanced Ctrl-R for shell history with n-dimensional search
+///
 /// The `ctrlrs` tool provides a powerful alternative to the standard Ctrl-R
 /// reverse history search in shells, with support for multiple nested filters.
 #[derive(Parser, Debug)]
@@ -18,17 +21,102 @@ struct Args {
     #[clap(short, long)]
     debug: bool,
 
+    /// Read settings from this file instead of ~/.config/ctrlrs/config.toml
+    #[clap(short, long)]
+    config: Option<PathBuf>,
+
     /// Specify shell type (auto-detected if not specified)
     #[clap(short, long)]
     shell: Option<String>,
 
-    /// Specify history file path (auto-detected if not specified)
+    /// Specify history file path or glob (auto-detected if not specified).
+    /// Repeat to merge several files, prefixing one with `bash:`, `zsh:` or
+    /// `fish:` to name its format
     #[clap(short = 'f', long)]
-    history_file: Option<String>,
-    
+    history_file: Vec<String>,
+
+    /// Start in fuzzy matching mode (toggle with Ctrl+F)
+    #[clap(long, overrides_with = "no_fuzzy")]
+    fuzzy: bool,
+
+    /// Start in substring matching mode, overriding the config file
+    #[clap(long, overrides_with = "fuzzy")]
+    no_fuzzy: bool,
+
+    /// Match case-sensitively once a filter contains an uppercase letter
+    #[clap(long, overrides_with = "no_smart_case")]
+    smart_case: bool,
+
+    /// Ignore case by default, overriding the config file
+    #[clap(long, overrides_with = "smart_case")]
+    no_smart_case: bool,
+
+    /// Only match whole words by default
+    #[clap(short = 'w', long, overrides_with = "no_word")]
+    word: bool,
+
+    /// Match anywhere in a word by default, overriding the config file
+    #[clap(long, overrides_with = "word")]
+    no_word: bool,
+
+    /// How repeated commands are merged: none, exact (default) or whitespace
+    #[clap(long)]
+    dedup: Option<String>,
+
+    /// How results are ordered: recency (default) or frecency (toggle with Alt+S)
+    #[clap(long)]
+    rank: Option<String>,
+
+    /// Key bindings to use: emacs (default) or vi
+    #[clap(long)]
+    keymap: Option<String>,
+
+    /// Parse the whole history file instead of using the cache
+    #[clap(long, overrides_with = "cache")]
+    no_cache: bool,
+
+    /// Use the cache of parsed histories, overriding the config file
+    #[clap(long, overrides_with = "no_cache")]
+    cache: bool,
+
+    /// Leave the mouse to the terminal so text can be selected
+    #[clap(long, overrides_with = "mouse")]
+    no_mouse: bool,
+
+    /// Handle the mouse in the picker, overriding the config file
+    #[clap(long, overrides_with = "no_mouse")]
+    mouse: bool,
+
+    /// Draw the picker below the prompt in this many rows, or a percentage
+    /// of the terminal such as 40%, instead of taking the whole screen
+    #[clap(long, overrides_with = "fullscreen")]
+    height: Option<String>,
+
+    /// Take the whole screen, overriding a height from the config file
+    #[clap(long, overrides_with = "height")]
+    fullscreen: bool,
+
+    /// Show when each command last ran: none (default), relative or absolute
+    /// (cycle with Alt+T)
+    #[clap(long)]
+    timestamps: Option<String>,
+
+    /// Start with the preview of the selected command shown (toggle with Alt+P)
+    #[clap(long, overrides_with = "no_preview")]
+    preview: bool,
+
+    /// Start with the preview hidden, overriding the config file
+    #[clap(long, overrides_with = "preview")]
+    no_preview: bool,
+
     /// Specify output file path for the selected command
     #[clap(short = 'o', long)]
     output_file: Option<String>,
+
+    /// Terminate the selected command with a NUL byte instead of a newline,
+    /// so multi-line commands can be read back exactly
+    #[clap(short = 'z', long)]
+    null: bool,
 }
 
 fn main() -> Result<()> {
@@ -39,7 +127,7 @@ fn main() -> Result<()> {
         env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug"))
             .target(env_logger::Target::Stderr)
             .init();
-        
+
         log::info!("Starting ctrlrs");
     } else {
         // Disable logging
@@ -47,20 +135,29 @@ fn main() -> Result<()> {
             .target(env_logger::Target::Stderr)
             .init();
     }
-    
-    // Load configuration
-    let config = Config::new(args.shell.clone(), args.history_file.clone())?;
-    
+
+    // Load configuration, reporting a broken config file or an invalid
+    // option before the picker starts
+    let config = match load_config(&args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(2);
+        }
+    };
+
     if args.debug {
         log::info!("Configuration loaded: {:?}", config);
     }
 
-    // Setup terminal
-    let mut terminal = setup_terminal()?;
-
-    // Create app state
+    // Create app state before touching the terminal, so a history file that
+    // can't be opened is reported on a normal screen
+    let (mouse, height) = (config.mouse, config.height);
     let mut app = App::new(config)?;
 
+    // Setup terminal
+    let (mut terminal, terminal_mode) = setup_terminal(mouse, height)?;
+
     // Run the application
     let res = run_app(&mut terminal, &mut app);
 
@@ -68,7 +165,7 @@ fn main() -> Result<()> {
     let selected_command = app.selected_command();
 
     // Restore terminal
-    restore_terminal(&mut terminal)?;
+    restore_terminal(&mut terminal, terminal_mode)?;
 
     // Handle application result
     if let Err(err) = res {
@@ -78,25 +175,104 @@ fn main() -> Result<()> {
 
     // Write the selected command to the specified output file or a temporary file
     if let Some(cmd) = selected_command {
+        let terminator = if args.null { '\0' } else { '\n' };
+
         if let Some(output_path) = &args.output_file {
             // Write to the specified output file
             match std::fs::File::create(output_path) {
                 Ok(mut file) => {
-                    if let Err(err) = writeln!(file, "{}", cmd) {
+                    if let Err(err) = write!(file, "{}{}", cmd, terminator) {
                         eprintln!("Error writing to output file: {}", err);
-                        return Err(ctrlrs::error::Error::Other(format!("Failed to write to output file: {}", err)));
+                        return Err(ctrlrs::error::Error::Other(format!(
+                            "Failed to write to output file: {}",
+                            err
+                        )));
                     }
-                },
+                }
                 Err(err) => {
                     eprintln!("Error creating output file: {}", err);
-                    return Err(ctrlrs::error::Error::Other(format!("Failed to create output file: {}", err)));
+                    return Err(ctrlrs::error::Error::Other(format!(
+                        "Failed to create output file: {}",
+                        err
+                    )));
                 }
             }
         } else {
             // If no output file is specified, print to stdout (fallback for backward compatibility)
-            println!("{}", cmd);
+            print!("{}{}", cmd, terminator);
         }
     }
 
     Ok(())
 }
+
+/// Build the configuration from the config file and the command line options
+fn load_config(args: &Args) -> Result<Config> {
+    let mut config_file = ConfigFile::load(args.config.as_deref())?;
+    if let Some(keymap) = &args.keymap {
+        // The preset is applied before the file's own key bindings
+        config_file.keymap = Some(KeymapPreset::from_name(keymap).ok_or_else(|| {
+            ctrlrs::error::Error::Other(format!("Unsupported keymap: {}", keymap))
+        })?);
+    }
+    let mut config = Config::from_file(config_file, args.shell.clone(), args.history_file.clone())?;
+    if args.fuzzy {
+        config.match_mode = MatchMode::Fuzzy;
+    }
+    if args.no_fuzzy {
+        config.match_mode = MatchMode::Substring;
+    }
+    if args.smart_case {
+        config.case_mode = CaseMode::Smart;
+    }
+    if args.no_smart_case {
+        config.case_mode = CaseMode::Insensitive;
+    }
+    if args.word {
+        config.whole_word = true;
+    }
+    if args.no_word {
+        config.whole_word = false;
+    }
+    if let Some(dedup) = &args.dedup {
+        config.dedup = DedupMode::from_name(dedup).ok_or_else(|| {
+            ctrlrs::error::Error::Other(format!("Unsupported dedup mode: {}", dedup))
+        })?;
+    }
+    if let Some(rank) = &args.rank {
+        config.rank_mode = RankMode::from_name(rank).ok_or_else(|| {
+            ctrlrs::error::Error::Other(format!("Unsupported rank mode: {}", rank))
+        })?;
+    }
+    if args.no_cache {
+        config.cache_dir = None;
+    }
+    if args.cache {
+        config.cache_dir = Config::default_cache_dir();
+    }
+    if args.no_mouse {
+        config.mouse = false;
+    }
+    if args.mouse {
+        config.mouse = true;
+    }
+    if let Some(timestamps) = &args.timestamps {
+        config.timestamps = TimestampMode::from_name(timestamps).ok_or_else(|| {
+            ctrlrs::error::Error::Other(format!("Unsupported timestamp mode: {}", timestamps))
+        })?;
+    }
+    if args.preview {
+        config.preview = true;
+    }
+    if args.no_preview {
+        config.preview = false;
+    }
+    if let Some(height) = &args.height {
+        config.height = Some(height.parse()?);
+    }
+    if args.fullscreen {
+        config.height = None;
+    }
+
+    Ok(config)
+}
@@ -0,0 +1,501 @@
+//! Matching filter text against history commands
+
+use regex::Regex;
+use serde::Deserialize;
+use std::ops::Range;
+
+/// Score for each matched pattern character
+const SCORE_MATCH: i64 = 16;
+/// Bonus when a matched character directly follows the previous match
+const BONUS_CONSECUTIVE: i64 = 8;
+/// Bonus when a matched character starts a word
+const BONUS_BOUNDARY: i64 = 10;
+/// Penalty for each unmatched character inside the matched window
+const PENALTY_GAP: i64 = 1;
+
+/// How filter text is matched against commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// Case-insensitive substring match
+    #[default]
+    Substring,
+    /// Fuzzy subsequence match, ranked by score (like fzf)
+    Fuzzy,
+}
+
+impl MatchMode {
+    /// Switch between substring and fuzzy matching
+    pub fn toggle_fuzzy(self) -> Self {
+        match self {
+            Self::Substring => Self::Fuzzy,
+            Self::Fuzzy => Self::Substring,
+        }
+    }
+
+    /// Short name shown in the UI
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Substring => "substring",
+            Self::Fuzzy => "fuzzy",
+        }
+    }
+}
+
+/// How letter case is treated when matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+    /// Always ignore case
+    #[default]
+    Insensitive,
+    /// Ignore case unless the pattern contains an uppercase letter
+    Smart,
+    /// Always match case exactly
+    Sensitive,
+}
+
+impl CaseMode {
+    /// Cycle to the next case mode
+    pub fn next(self) -> Self {
+        match self {
+            Self::Insensitive => Self::Smart,
+            Self::Smart => Self::Sensitive,
+            Self::Sensitive => Self::Insensitive,
+        }
+    }
+
+    /// Whether a pattern should be matched case-sensitively
+    ///
+    /// In a regex the character after a backslash is part of an escape like
+    /// `\S` or `\W`, so smart case does not count it as uppercase.
+    pub fn is_sensitive(&self, pattern: &str, regex: bool) -> bool {
+        match self {
+            Self::Insensitive => false,
+            Self::Smart if regex => {
+                let mut chars = pattern.chars();
+                while let Some(c) = chars.next() {
+                    if c == '\\' {
+                        chars.next();
+                    } else if c.is_uppercase() {
+                        return true;
+                    }
+                }
+                false
+            }
+            Self::Smart => pattern.chars().any(char::is_uppercase),
+            Self::Sensitive => true,
+        }
+    }
+
+    /// Short name shown in the UI
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Insensitive => "ignore-case",
+            Self::Smart => "smart-case",
+            Self::Sensitive => "case-sensitive",
+        }
+    }
+}
+
+/// The filter typed into one dimension, with its options
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// The filter text
+    pub text: String,
+    /// Treat the text as a raw regular expression
+    pub regex: bool,
+    /// Invert whether matching commands are kept, on top of a leading `!`
+    pub negated: bool,
+    /// How letter case is treated
+    pub case: CaseMode,
+    /// Only match whole words (substring and regex matching)
+    pub whole_word: bool,
+}
+
+impl Filter {
+    /// Create a plain filter from text
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the filter has no pattern to match
+    pub fn is_empty(&self) -> bool {
+        self.pattern().is_empty()
+    }
+
+    /// The text to match, without a leading `!`
+    pub fn pattern(&self) -> &str {
+        self.text.strip_prefix('!').unwrap_or(&self.text)
+    }
+
+    /// Whether matching commands are excluded
+    ///
+    /// A leading `!` excludes them and the toggle inverts that, so it can also
+    /// turn a typed `!` off.
+    pub fn is_negated(&self) -> bool {
+        self.negated != self.text.starts_with('!')
+    }
+
+    /// Describe the effective match mode and options, shown in the UI
+    pub fn describe(&self, mode: MatchMode) -> String {
+        let mut parts = vec![if self.regex { "regex" } else { mode.name() }];
+        if self.case != CaseMode::Insensitive {
+            parts.push(self.case.name());
+        }
+        if self.whole_word {
+            parts.push("word");
+        }
+        if self.is_negated() {
+            parts.push("exclude");
+        }
+        parts.join(", ")
+    }
+}
+
+/// Where and how well a filter matched a command
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Match score (higher is better)
+    pub score: i64,
+    /// Matched byte ranges in the command
+    pub ranges: Vec<Range<usize>>,
+}
+
+/// A filter compiled for repeated matching
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    /// Substring or regex match
+    Regex(Regex),
+    /// Fuzzy match on the pattern characters
+    Fuzzy {
+        /// The pattern characters, lowercased unless matching case-sensitively
+        pattern: Vec<char>,
+        /// Whether characters must match case exactly
+        case_sensitive: bool,
+    },
+}
+
+impl Matcher {
+    /// Compile a filter for the given mode
+    ///
+    /// Only regex filters can fail to compile.
+    pub fn new(filter: &Filter, mode: MatchMode) -> std::result::Result<Self, regex::Error> {
+        let pattern = filter.pattern();
+        let case_sensitive = filter.case.is_sensitive(pattern, filter.regex);
+        let flags = if case_sensitive { "" } else { "(?i)" };
+
+        if filter.regex {
+            let body = if filter.whole_word {
+                format!(r"\b(?:{})\b", pattern)
+            } else {
+                pattern.to_string()
+            };
+            return Ok(Self::Regex(Regex::new(&format!("{}{}", flags, body))?));
+        }
+
+        match mode {
+            MatchMode::Substring => {
+                let mut body = regex::escape(pattern);
+                if filter.whole_word {
+                    body = word_bounded(pattern, &body);
+                }
+                Ok(Self::Regex(Regex::new(&format!("{}{}", flags, body))?))
+            }
+            MatchMode::Fuzzy => Ok(Self::Fuzzy {
+                pattern: if case_sensitive {
+                    pattern.chars().collect()
+                } else {
+                    pattern.chars().flat_map(char::to_lowercase).collect()
+                },
+                case_sensitive,
+            }),
+        }
+    }
+
+    /// Whether results from this matcher should be ordered by score
+    pub fn is_ranked(&self) -> bool {
+        matches!(self, Self::Fuzzy { .. })
+    }
+
+    /// Match a command, returning its score (higher is better)
+    pub fn score(&self, text: &str) -> Option<i64> {
+        match self {
+            Self::Regex(re) => re.is_match(text).then_some(0),
+            Self::Fuzzy {
+                pattern,
+                case_sensitive,
+            } => fuzzy_match(pattern, text, *case_sensitive).map(|m| m.score),
+        }
+    }
+
+    /// Match a command, returning its score and the matched ranges
+    pub fn find(&self, text: &str) -> Option<Match> {
+        match self {
+            Self::Regex(re) => {
+                let ranges: Vec<Range<usize>> = re
+                    .find_iter(text)
+                    .map(|m| m.range())
+                    .filter(|range| !range.is_empty())
+                    .collect();
+                // A regex that only matches the empty string still matches
+                (!ranges.is_empty() || re.is_match(text)).then_some(Match { score: 0, ranges })
+            }
+            Self::Fuzzy {
+                pattern,
+                case_sensitive,
+            } => fuzzy_match(pattern, text, *case_sensitive),
+        }
+    }
+}
+
+/// Add `\b` to the ends of an escaped literal that start or end with a word
+/// character, so `ls` won't match inside `tools` but `-la` still matches
+fn word_bounded(pattern: &str, escaped: &str) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(pattern.chars().next()) {
+        r"\b"
+    } else {
+        ""
+    };
+    let end = if is_word(pattern.chars().last()) {
+        r"\b"
+    } else {
+        ""
+    };
+    format!("{}{}{}", start, escaped, end)
+}
+
+/// Match a fuzzy subsequence pattern against text
+///
+/// Finds the leftmost complete match, then walks back from its end to the
+/// shortest window that still contains the pattern, and scores that window.
+/// Unless `case_sensitive` is set the pattern must already be lowercased.
+fn fuzzy_match(pattern: &[char], text: &str, case_sensitive: bool) -> Option<Match> {
+    if pattern.is_empty() {
+        return Some(Match {
+            score: 0,
+            ranges: Vec::new(),
+        });
+    }
+
+    let (offsets, chars): (Vec<usize>, Vec<char>) = text.char_indices().unzip();
+    let matches =
+        |c: char, p: char| c == p || (!case_sensitive && c.to_lowercase().eq(std::iter::once(p)));
+
+    // Forward pass: where does the leftmost match end?
+    let mut pi = 0;
+    let mut end = None;
+    for (i, &c) in chars.iter().enumerate() {
+        if matches(c, pattern[pi]) {
+            pi += 1;
+            if pi == pattern.len() {
+                end = Some(i);
+                break;
+            }
+        }
+    }
+    let end = end?;
+
+    // Backward pass: shrink the window from the left
+    let mut pi = pattern.len() - 1;
+    let mut start = 0;
+    for i in (0..=end).rev() {
+        if matches(chars[i], pattern[pi]) {
+            if pi == 0 {
+                start = i;
+                break;
+            }
+            pi -= 1;
+        }
+    }
+
+    // Score the window, merging adjacent matched characters into ranges
+    let mut score = 0;
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut pi = 0;
+    let mut previous_match: Option<usize> = None;
+    for i in start..=end {
+        if pi < pattern.len() && matches(chars[i], pattern[pi]) {
+            let char_range = offsets[i]..offsets[i] + chars[i].len_utf8();
+            score += SCORE_MATCH;
+            if previous_match.is_some_and(|prev| prev + 1 == i) {
+                score += BONUS_CONSECUTIVE;
+                if let Some(last) = ranges.last_mut() {
+                    last.end = char_range.end;
+                }
+            } else {
+                ranges.push(char_range);
+            }
+            if i == 0 || !chars[i - 1].is_alphanumeric() {
+                score += BONUS_BOUNDARY;
+            }
+            previous_match = Some(i);
+            pi += 1;
+        } else {
+            score -= PENALTY_GAP;
+        }
+    }
+
+    Some(Match { score, ranges })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_subsequence() {
+        let matcher = Matcher::new(&Filter::new("dkrps"), MatchMode::Fuzzy).unwrap();
+
+        assert!(matcher.score("docker ps").is_some());
+        assert!(matcher.score("Docker PS -a").is_some());
+        assert!(matcher.score("kubectl get pods").is_none());
+    }
+
+    #[test]
+    fn test_fuzzy_ranking() {
+        let matcher = Matcher::new(&Filter::new("gst"), MatchMode::Fuzzy).unwrap();
+
+        let boundary = matcher.score("git status").unwrap();
+        let scattered = matcher.score("cargo test").unwrap();
+        assert!(boundary > scattered);
+
+        let consecutive = matcher.score("gsteamer").unwrap();
+        let spread = matcher.score("g s t").unwrap();
+        assert!(consecutive > matcher.score("gxsxtx").unwrap());
+        assert!(spread > matcher.score("gasatx").unwrap());
+    }
+
+    #[test]
+    fn test_regex_filter() {
+        let filter = Filter {
+            text: r"^git (push|pull)".to_string(),
+            regex: true,
+            ..Filter::default()
+        };
+        let matcher = Matcher::new(&filter, MatchMode::Fuzzy).unwrap();
+
+        assert!(matcher.score("git push origin main").is_some());
+        assert!(matcher.score("GIT PULL").is_some());
+        assert!(matcher.score("echo git push").is_none());
+
+        let plain = Matcher::new(&Filter::new(r"\.tar\.gz$"), MatchMode::Substring).unwrap();
+        assert!(plain.score("tar xzf a.tar.gz").is_none());
+
+        let invalid = Filter {
+            text: "(unclosed".to_string(),
+            regex: true,
+            ..Filter::default()
+        };
+        assert!(Matcher::new(&invalid, MatchMode::Substring).is_err());
+    }
+
+    #[test]
+    fn test_negated_filter() {
+        let prefixed = Filter::new("!get");
+        assert!(prefixed.is_negated());
+        assert_eq!(prefixed.pattern(), "get");
+
+        let toggled = Filter {
+            text: "get".to_string(),
+            negated: true,
+            ..Filter::default()
+        };
+        assert!(toggled.is_negated());
+        assert_eq!(toggled.pattern(), "get");
+
+        // The toggle turns a typed `!` off again
+        let untoggled = Filter {
+            negated: true,
+            ..Filter::new("!get")
+        };
+        assert!(!untoggled.is_negated());
+        assert_eq!(untoggled.pattern(), "get");
+
+        assert!(Filter::new("!").is_empty());
+        assert!(!Filter::new("get").is_negated());
+    }
+
+    #[test]
+    fn test_smart_case() {
+        let smart = |text: &str| Filter {
+            text: text.to_string(),
+            case: CaseMode::Smart,
+            ..Filter::default()
+        };
+
+        let lower = Matcher::new(&smart("readme"), MatchMode::Substring).unwrap();
+        assert!(lower.score("cat README.md").is_some());
+
+        let upper = Matcher::new(&smart("README"), MatchMode::Substring).unwrap();
+        assert!(upper.score("cat README.md").is_some());
+        assert!(upper.score("cat readme.md").is_none());
+
+        let fuzzy = Matcher::new(&smart("RM"), MatchMode::Fuzzy).unwrap();
+        assert!(fuzzy.score("cat README.md").is_some());
+        assert!(fuzzy.score("cat readme.md").is_none());
+    }
+
+    #[test]
+    fn test_smart_case_regex_escapes() {
+        let smart_regex = |text: &str| Filter {
+            text: text.to_string(),
+            regex: true,
+            case: CaseMode::Smart,
+            ..Filter::default()
+        };
+
+        // Escapes like \S and \D are not uppercase letters typed by the user
+        let escaped = Matcher::new(&smart_regex(r"readme\S*\.md"), MatchMode::Substring).unwrap();
+        assert!(escaped.score("cat README.md").is_some());
+        assert!(!CaseMode::Smart.is_sensitive(r"\W\D\S", true));
+        assert!(CaseMode::Smart.is_sensitive(r"\W\D\S", false));
+
+        let upper = Matcher::new(&smart_regex(r"\bREADME\b"), MatchMode::Substring).unwrap();
+        assert!(upper.score("cat README.md").is_some());
+        assert!(upper.score("cat readme.md").is_none());
+    }
+
+    #[test]
+    fn test_whole_word() {
+        let word = |text: &str, regex: bool| Filter {
+            text: text.to_string(),
+            regex,
+            whole_word: true,
+            ..Filter::default()
+        };
+
+        let ls = Matcher::new(&word("ls", false), MatchMode::Substring).unwrap();
+        assert!(ls.score("ls -la").is_some());
+        assert!(ls.score("cd /tmp && ls").is_some());
+        assert!(ls.score("false").is_none());
+        assert!(ls.score("cargo install tools").is_none());
+
+        let flag = Matcher::new(&word("-la", false), MatchMode::Substring).unwrap();
+        assert!(flag.score("ls -la").is_some());
+        assert!(flag.score("ls -lah").is_none());
+
+        let regex = Matcher::new(&word("l[sn]", true), MatchMode::Substring).unwrap();
+        assert!(regex.score("ln -s a b").is_some());
+        assert!(regex.score("kiln").is_none());
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        let substring = Matcher::new(&Filter::new("ps"), MatchMode::Substring).unwrap();
+        let found = substring.find("ps aux | grep ps").unwrap();
+        assert_eq!(found.ranges, vec![0..2, 14..16]);
+
+        let fuzzy = Matcher::new(&Filter::new("dkrps"), MatchMode::Fuzzy).unwrap();
+        let found = fuzzy.find("docker ps").unwrap();
+        assert_eq!(found.ranges, vec![0..1, 3..4, 5..6, 7..9]);
+
+        // Ranges are byte offsets, so they stay on character boundaries
+        let found = substring.find("écho ps").unwrap();
+        assert_eq!(found.ranges, vec![6..8]);
+    }
+}
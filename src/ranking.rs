@@ -0,0 +1,218 @@
+//! Ordering of history entries before filtering
+//!
+//! Results are listed in the order of the history, and filters only re-rank
+//! them in fuzzy mode. By default the history is ordered newest first;
+//! frecency instead combines how often and how recently a command ran.
+
+use crate::history::HistoryEntry;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::env;
+use std::path::{Component, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds in an hour
+const HOUR: u64 = 60 * 60;
+/// Seconds in a day
+const DAY: u64 = 24 * HOUR;
+/// Seconds in a week
+const WEEK: u64 = 7 * DAY;
+
+/// How the history is ordered
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RankMode {
+    /// Newest first
+    #[default]
+    Recency,
+    /// Most frequently and recently run first
+    Frecency,
+}
+
+impl RankMode {
+    /// Switch between recency and frecency
+    pub fn toggle(self) -> Self {
+        match self {
+            Self::Recency => Self::Frecency,
+            Self::Frecency => Self::Recency,
+        }
+    }
+
+    /// Get the rank mode from its name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "recency" | "recent" => Some(Self::Recency),
+            "frecency" | "frecent" => Some(Self::Frecency),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the rank mode
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Recency => "recency",
+            Self::Frecency => "frecency",
+        }
+    }
+}
+
+/// Scores history entries by frecency
+///
+/// Each run of a command counts for more the more recently the command was
+/// last run, so a command run ten times a day beats a one-off from five
+/// minutes ago. Commands that refer to paths in the current directory, as
+/// recorded by fish, get a further boost.
+#[derive(Debug, Clone)]
+pub struct Frecency {
+    /// The current time in seconds since the epoch
+    now: u64,
+    /// The current directory, if known
+    current_dir: Option<PathBuf>,
+    /// Whether each path seen so far exists in the current directory, so
+    /// ranking again does not touch the filesystem
+    in_dir: HashMap<String, bool>,
+}
+
+impl Frecency {
+    /// Score entries relative to the current time and directory
+    pub fn new() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default();
+        Self::at(now, env::current_dir().ok())
+    }
+
+    /// Score entries relative to a given time and directory
+    pub fn at(now: u64, current_dir: Option<PathBuf>) -> Self {
+        Self {
+            now,
+            current_dir,
+            in_dir: HashMap::new(),
+        }
+    }
+
+    /// Get the frecency score of an entry
+    pub fn score(&mut self, entry: &HistoryEntry) -> u64 {
+        // Entries without a timestamp count as old
+        let weight = match entry.timestamp.map(|ts| self.now.saturating_sub(ts)) {
+            Some(age) if age < HOUR => 16,
+            Some(age) if age < DAY => 8,
+            Some(age) if age < WEEK => 2,
+            _ => 1,
+        };
+
+        let boost = if self.in_current_dir(entry) { 2 } else { 1 };
+
+        entry.count as u64 * weight * boost
+    }
+
+    /// Order entries by descending score, keeping the current order for equal scores
+    pub fn rank(&mut self, entries: &mut [Arc<HistoryEntry>]) {
+        entries.sort_by_cached_key(|entry| Reverse(self.score(entry)));
+    }
+
+    /// Whether an entry refers to a path that exists in the current directory
+    ///
+    /// Absolute paths only count when they lie under the current directory,
+    /// and relative paths only when they do not leave it.
+    fn in_current_dir(&mut self, entry: &HistoryEntry) -> bool {
+        let Some(dir) = &self.current_dir else {
+            return false;
+        };
+        entry.paths.iter().any(|path| {
+            *self.in_dir.entry(path.clone()).or_insert_with(|| {
+                let path = dir.join(path);
+                path.starts_with(dir)
+                    && !path
+                        .components()
+                        .any(|component| component == Component::ParentDir)
+                    && path.exists()
+            })
+        })
+    }
+}
+
+impl Default for Frecency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn entry(command: &str, timestamp: u64, count: usize) -> Arc<HistoryEntry> {
+        Arc::new(HistoryEntry {
+            command: command.to_string(),
+            timestamp: Some(timestamp),
+            count,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_frecency_prefers_frequent_commands() {
+        let now = 1_700_000_000;
+        let mut frecency = Frecency::at(now, None);
+
+        let mut entries = vec![
+            entry("one-off", now - 5 * 60, 1),
+            entry("cargo test", now - 3 * HOUR, 10),
+            entry("old favourite", now - 30 * DAY, 40),
+            entry("last week", now - 2 * DAY, 1),
+        ];
+        frecency.rank(&mut entries);
+
+        let commands: Vec<&str> = entries.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            vec!["cargo test", "old favourite", "one-off", "last week"]
+        );
+    }
+
+    #[test]
+    fn test_frecency_boosts_current_dir() {
+        let dir = tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "").unwrap();
+        let mut frecency = Frecency::at(0, Some(dir.path().to_path_buf()));
+
+        let mut here = HistoryEntry {
+            command: "cat notes.txt".to_string(),
+            paths: vec!["notes.txt".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(frecency.score(&here), 2);
+
+        here.paths = vec!["elsewhere.txt".to_string()];
+        assert_eq!(frecency.score(&here), 1);
+
+        // Absolute paths count only inside the current directory
+        here.paths = vec![dir.path().join("notes.txt").display().to_string()];
+        assert_eq!(frecency.score(&here), 2);
+    }
+
+    #[test]
+    fn test_frecency_ignores_paths_outside_current_dir() {
+        let parent = tempdir().unwrap();
+        let dir = parent.path().join("project");
+        std::fs::create_dir(&dir).unwrap();
+        std::fs::write(parent.path().join("hosts"), "").unwrap();
+        let mut frecency = Frecency::at(0, Some(dir.clone()));
+
+        let outside = parent.path().join("hosts");
+        assert!(outside.is_absolute() && outside.exists());
+        for path in [outside.display().to_string(), "../hosts".to_string()] {
+            let entry = HistoryEntry {
+                command: "cat hosts".to_string(),
+                paths: vec![path],
+                ..Default::default()
+            };
+            assert_eq!(frecency.score(&entry), 1);
+        }
+    }
+}
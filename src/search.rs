@@ -0,0 +1,420 @@
+//! Incremental filtering across dimensions
+
+use crate::error::Error;
+use crate::history::{DurationFilter, FilteredEntry, Highlight, HistoryManager};
+use crate::matcher::{Filter, MatchMode, Matcher};
+use crate::Result;
+use std::cmp::Reverse;
+
+/// How one dimension decides which entries pass
+#[derive(Debug, Clone)]
+enum DimensionMatcher {
+    /// An empty filter, everything passes
+    All,
+    /// A `dur>10s`-style filter on command duration
+    Duration(DurationFilter),
+    /// A compiled text matcher
+    Text(Matcher),
+}
+
+/// Cached state of one filter dimension
+#[derive(Debug, Clone)]
+struct Dimension {
+    /// The filter that produced this state
+    filter: Filter,
+    /// The compiled filter
+    matcher: DimensionMatcher,
+    /// Entries that passed this dimension, in result order
+    passed: Vec<FilteredEntry>,
+    /// Position of each passed entry in this dimension's input, used to keep
+    /// ties in input order when ranking
+    positions: Vec<usize>,
+}
+
+/// Incremental search over a history
+///
+/// Each dimension keeps its compiled matcher and its result. Unchanged leading
+/// dimensions are reused as they are, and when the first changed filter only
+/// grew, that dimension narrows its own previous result instead of scanning
+/// its whole input again.
+#[derive(Debug, Clone, Default)]
+pub struct Search {
+    /// The match mode the cached dimensions were built with
+    mode: MatchMode,
+    /// Every history entry, unfiltered
+    all: Vec<FilteredEntry>,
+    /// Cached dimensions, in order
+    dimensions: Vec<Dimension>,
+    /// Entries the last update ran a dimension over, to check what was reused
+    #[cfg(test)]
+    scanned: usize,
+}
+
+impl Search {
+    /// Create an empty search
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget all cached results, e.g. after the history changed
+    pub fn invalidate(&mut self) {
+        self.all.clear();
+        self.dimensions.clear();
+    }
+
+    /// Get the entries that passed every dimension
+    pub fn results(&self) -> &[FilteredEntry] {
+        match self.dimensions.last() {
+            Some(dimension) => &dimension.passed,
+            None => &self.all,
+        }
+    }
+
+    /// Take the entries that passed every dimension
+    pub fn into_results(mut self) -> Vec<FilteredEntry> {
+        match self.dimensions.pop() {
+            Some(dimension) => dimension.passed,
+            None => self.all,
+        }
+    }
+
+    /// Apply filters, reusing as much of the previous search as possible
+    ///
+    /// Returns `Error::InvalidPattern` if a regex filter does not compile. The
+    /// previous results are then kept.
+    pub fn update(
+        &mut self,
+        history: &HistoryManager,
+        filters: &[Filter],
+        mode: MatchMode,
+    ) -> Result<()> {
+        #[cfg(test)]
+        {
+            self.scanned = 0;
+        }
+
+        // A new match mode or history invalidates every cached dimension, but
+        // only once the new filters are known to compile
+        let mode_changed = mode != self.mode;
+        let history_changed = self.all.len() != history.entries().len();
+        let reusable = if mode_changed || history_changed {
+            &[][..]
+        } else {
+            &self.dimensions[..]
+        };
+
+        // If every filter is empty, everything matches
+        let filters = if filters.iter().all(Filter::is_empty) {
+            &[]
+        } else {
+            filters
+        };
+
+        // Compile the changed dimensions first so an invalid pattern leaves the
+        // cache untouched
+        let unchanged = reusable
+            .iter()
+            .zip(filters)
+            .take_while(|(dimension, filter)| dimension.filter == **filter)
+            .count();
+        let matchers = filters
+            .iter()
+            .enumerate()
+            .skip(unchanged)
+            .map(|(dim, filter)| match reusable.get(dim) {
+                Some(cached) if cached.filter == *filter => Ok(cached.matcher.clone()),
+                _ => Self::compile(dim, filter, mode),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.mode = mode;
+        if history_changed {
+            self.invalidate();
+            self.all = history
+                .entries()
+                .iter()
+                .map(|entry| FilteredEntry {
+                    entry: entry.clone(),
+                    highlights: Vec::new(),
+                })
+                .collect();
+        } else if mode_changed {
+            self.dimensions.clear();
+        }
+
+        let mut stale = self.dimensions.split_off(unchanged);
+
+        for ((dim, filter), matcher) in filters.iter().enumerate().skip(unchanged).zip(matchers) {
+            // A filter that only grew can narrow its own previous result
+            let narrowed =
+                if dim == unchanged && !stale.is_empty() && Self::narrows(&stale[0].filter, filter)
+                {
+                    Some(stale.swap_remove(0))
+                } else {
+                    None
+                };
+
+            #[cfg(test)]
+            {
+                self.scanned += match (&narrowed, dim) {
+                    (Some(previous), _) => previous.passed.len(),
+                    (None, 0) => self.all.len(),
+                    (None, _) => self.dimensions[dim - 1].passed.len(),
+                };
+            }
+
+            let (passed, positions) = match &narrowed {
+                Some(previous) => Self::apply(
+                    dim,
+                    filter,
+                    &matcher,
+                    previous
+                        .positions
+                        .iter()
+                        .copied()
+                        .zip(previous.passed.iter()),
+                ),
+                None => {
+                    let input = match dim {
+                        0 => &self.all,
+                        _ => &self.dimensions[dim - 1].passed,
+                    };
+                    Self::apply(dim, filter, &matcher, input.iter().enumerate())
+                }
+            };
+
+            self.dimensions.push(Dimension {
+                filter: filter.clone(),
+                matcher,
+                passed,
+                positions,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Compile the filter of one dimension
+    fn compile(dimension: usize, filter: &Filter, mode: MatchMode) -> Result<DimensionMatcher> {
+        if filter.is_empty() {
+            return Ok(DimensionMatcher::All);
+        }
+
+        if let Some(duration_filter) =
+            DurationFilter::parse(filter.pattern()).filter(|_| !filter.regex)
+        {
+            return duration_filter
+                .map(DimensionMatcher::Duration)
+                .map_err(|message| Error::InvalidPattern { dimension, message });
+        }
+
+        Matcher::new(filter, mode)
+            .map(DimensionMatcher::Text)
+            .map_err(|e| Error::InvalidPattern {
+                dimension,
+                // The last line of a regex error says what is wrong
+                message: e
+                    .to_string()
+                    .lines()
+                    .last()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+            })
+    }
+
+    /// Whether every match of `new` is also a match of `old`, so `new` can be
+    /// applied to the result of `old` instead of its input
+    fn narrows(old: &Filter, new: &Filter) -> bool {
+        // Regexes, whole words and exclusions can all match more as they grow
+        !old.regex
+            && !new.regex
+            && !old.whole_word
+            && !new.whole_word
+            && !old.is_negated()
+            && !new.is_negated()
+            && old.case == new.case
+            && DurationFilter::parse(old.pattern()).is_none()
+            && DurationFilter::parse(new.pattern()).is_none()
+            && new.pattern().starts_with(old.pattern())
+    }
+
+    /// Run one dimension over its input, returning the passed entries and their
+    /// input positions
+    ///
+    /// Ranked matchers sort by score, keeping input order for equal scores.
+    fn apply<'a>(
+        dimension: usize,
+        filter: &Filter,
+        matcher: &DimensionMatcher,
+        input: impl Iterator<Item = (usize, &'a FilteredEntry)>,
+    ) -> (Vec<FilteredEntry>, Vec<usize>) {
+        let negated = filter.is_negated();
+        let mut ranked = false;
+
+        let mut scored: Vec<(i64, usize, FilteredEntry)> = match matcher {
+            DimensionMatcher::All => input
+                .map(|(position, filtered)| (0, position, filtered.clone()))
+                .collect(),
+            DimensionMatcher::Duration(duration_filter) => input
+                .filter(|(_, filtered)| duration_filter.matches(filtered.entry.duration) != negated)
+                .map(|(position, filtered)| (0, position, filtered.clone()))
+                .collect(),
+            // Excluding dimensions only drop entries, they never rank or highlight
+            DimensionMatcher::Text(text) if negated => input
+                .filter(|(_, filtered)| text.score(&filtered.entry.command).is_none())
+                .map(|(position, filtered)| (0, position, filtered.clone()))
+                .collect(),
+            DimensionMatcher::Text(text) => {
+                ranked = text.is_ranked();
+                input
+                    .filter_map(|(position, filtered)| {
+                        let found = text.find(&filtered.entry.command)?;
+                        let mut filtered = filtered.clone();
+                        // Drop highlights left over from a narrowed result
+                        filtered.highlights.retain(|h| h.dimension < dimension);
+                        filtered.highlights.extend(
+                            found
+                                .ranges
+                                .into_iter()
+                                .map(|range| Highlight { dimension, range }),
+                        );
+                        Some((found.score, position, filtered))
+                    })
+                    .collect()
+            }
+        };
+
+        if ranked {
+            scored.sort_by_key(|(score, position, _)| (Reverse(*score), *position));
+        }
+
+        scored
+            .into_iter()
+            .map(|(_, position, filtered)| (filtered, position))
+            .unzip()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::HistoryEntry;
+
+    fn history(commands: &[&str]) -> HistoryManager {
+        let entries = commands
+            .iter()
+            .map(|command| HistoryEntry {
+                command: command.to_string(),
+                full_command: command.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        HistoryManager::from_entries(entries, Vec::new())
+    }
+
+    fn commands(search: &Search) -> Vec<&str> {
+        search
+            .results()
+            .iter()
+            .map(|f| f.entry.command.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_incremental_matches_scratch() {
+        let history = history(&[
+            "docker ps -a",
+            "git checkout docs",
+            "docker compose up",
+            "kubectl get pods",
+            "docker ps",
+        ]);
+
+        for mode in [MatchMode::Substring, MatchMode::Fuzzy] {
+            let mut search = Search::new();
+            for typed in ["d", "do", "dock", "dockps", "docker p"] {
+                let filters = [Filter::new(typed)];
+                search.update(&history, &filters, mode).unwrap();
+
+                let scratch = history.filter_matches(&filters, mode).unwrap();
+                let expected: Vec<&str> =
+                    scratch.iter().map(|f| f.entry.command.as_str()).collect();
+                assert_eq!(commands(&search), expected, "{:?} {}", mode, typed);
+            }
+        }
+    }
+
+    #[test]
+    fn test_narrowing_reuses_previous_result() {
+        let history = history(&["cargo build", "cargo test", "git status"]);
+        let mut search = Search::new();
+
+        search
+            .update(&history, &[Filter::new("cargo")], MatchMode::Substring)
+            .unwrap();
+        assert_eq!(commands(&search), vec!["cargo build", "cargo test"]);
+        assert_eq!(search.scanned, 3);
+
+        // The first dimension narrows its own two results instead of the
+        // whole history, and the second one is recomputed from them
+        let filters = [Filter::new("cargo t"), Filter::new("!build")];
+        search
+            .update(&history, &filters, MatchMode::Substring)
+            .unwrap();
+        assert_eq!(commands(&search), vec!["cargo test"]);
+        assert_eq!(
+            search.results()[0].highlights,
+            vec![Highlight {
+                dimension: 0,
+                range: 0..7
+            }]
+        );
+        assert_eq!(search.scanned, 2 + 1);
+
+        // An unchanged first dimension is not run again
+        let filters = [Filter::new("cargo t"), Filter::new("!bu")];
+        search
+            .update(&history, &filters, MatchMode::Substring)
+            .unwrap();
+        assert_eq!(commands(&search), vec!["cargo test"]);
+        assert_eq!(search.scanned, 1);
+
+        // Shrinking a filter falls back to its full input
+        search
+            .update(&history, &[Filter::new("c")], MatchMode::Substring)
+            .unwrap();
+        assert_eq!(commands(&search), vec!["cargo build", "cargo test"]);
+        assert_eq!(search.scanned, 3);
+    }
+
+    #[test]
+    fn test_invalid_pattern_keeps_results() {
+        let history = history(&["git push", "git pull"]);
+        let mut search = Search::new();
+
+        search
+            .update(&history, &[Filter::new("push")], MatchMode::Substring)
+            .unwrap();
+
+        let invalid = Filter {
+            text: "(push".to_string(),
+            regex: true,
+            ..Filter::default()
+        };
+        let filters = [Filter::new("push"), invalid];
+        assert!(search
+            .update(&history, &filters[1..], MatchMode::Substring)
+            .is_err());
+        assert_eq!(commands(&search), vec!["git push"]);
+
+        // Switching the match mode with an invalid pattern keeps them too
+        assert!(search.update(&history, &filters, MatchMode::Fuzzy).is_err());
+        assert_eq!(commands(&search), vec!["git push"]);
+        search
+            .update(&history, &filters[..1], MatchMode::Fuzzy)
+            .unwrap();
+        assert_eq!(commands(&search), vec!["git push"]);
+        assert_eq!(search.scanned, 2);
+    }
+}
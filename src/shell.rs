@@ -3,8 +3,11 @@
 use crate::error::Error;
 use crate::Result;
 use dirs::home_dir;
+use serde::{Deserialize, Serialize};
 use std::env;
-use std::path::PathBuf;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 /// Supported shell types
@@ -29,7 +32,10 @@ impl ShellType {
         }
 
         // Try to get the shell from the process tree
-        if let Ok(output) = Command::new("ps").args(["-p", "$PPID", "-o", "comm="]).output() {
+        if let Ok(output) = Command::new("ps")
+            .args(["-p", "$PPID", "-o", "comm="])
+            .output()
+        {
             if let Ok(shell) = String::from_utf8(output.stdout) {
                 let shell = shell.trim();
                 if let Some(shell_type) = Self::from_name(shell) {
@@ -72,8 +78,9 @@ impl ShellType {
 
     /// Get the default history file path for this shell type
     pub fn default_history_path(&self) -> Result<PathBuf> {
-        let home = home_dir().ok_or_else(|| Error::Other("Could not determine home directory".to_string()))?;
-        
+        let home = home_dir()
+            .ok_or_else(|| Error::Other("Could not determine home directory".to_string()))?;
+
         match self {
             Self::Bash => Ok(home.join(".bash_history")),
             Self::Zsh => Ok(home.join(".zsh_history")),
@@ -81,24 +88,125 @@ impl ShellType {
         }
     }
 
+    /// Guess which shell wrote a history file, from its name or first line
+    pub fn from_history_file(path: &Path) -> Option<Self> {
+        if let Some(shell_type) = path
+            .file_name()
+            .and_then(|name| Self::from_name(&name.to_string_lossy()))
+        {
+            return Some(shell_type);
+        }
+
+        let mut first_line = Vec::new();
+        BufReader::new(File::open(path).ok()?)
+            .read_until(b'\n', &mut first_line)
+            .ok()?;
+        if first_line.starts_with(b"- cmd:") {
+            Some(Self::Fish)
+        } else if first_line.starts_with(b": ") && first_line.get(2).is_some_and(u8::is_ascii_digit)
+        {
+            Some(Self::Zsh)
+        } else if first_line.starts_with(b"#") && first_line.get(1).is_some_and(u8::is_ascii_digit)
+        {
+            Some(Self::Bash)
+        } else {
+            None
+        }
+    }
+
     /// Get the history file format for this shell type
     pub fn history_format(&self) -> HistoryFormat {
         match self {
-            Self::Bash => HistoryFormat::Plain,
+            Self::Bash => HistoryFormat::BashTimestamped,
             Self::Zsh => HistoryFormat::ZshExtended,
             Self::Fish => HistoryFormat::Fish,
         }
     }
 }
 
+/// A history file and the shell that wrote it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistorySource {
+    /// The history file
+    pub path: PathBuf,
+    /// The shell whose format the file is in
+    pub shell_type: ShellType,
+}
+
+impl HistorySource {
+    /// Create a history source
+    pub fn new(path: impl Into<PathBuf>, shell_type: ShellType) -> Self {
+        Self {
+            path: path.into(),
+            shell_type,
+        }
+    }
+
+    /// Expand a source specification into history sources
+    ///
+    /// A specification is a path or glob, optionally prefixed with the shell
+    /// that wrote it, such as `zsh:~/sync/*.hist`. Without a prefix the files
+    /// are read as `shell_type`, the shell the user asked for. Only when there
+    /// is none is the shell guessed from each file, falling back to `fallback`.
+    pub fn expand(
+        spec: &str,
+        shell_type: Option<ShellType>,
+        fallback: ShellType,
+    ) -> Result<Vec<Self>> {
+        let (shell_type, pattern) = match spec.split_once(':') {
+            Some((shell, pattern)) if ["bash", "zsh", "fish"].contains(&shell) => {
+                (ShellType::from_name(shell), pattern)
+            }
+            _ => (shell_type, spec),
+        };
+
+        let pattern = match (pattern.strip_prefix("~/"), home_dir()) {
+            (Some(rest), Some(home)) => home.join(rest).to_string_lossy().into_owned(),
+            _ => pattern.to_string(),
+        };
+
+        let paths = if pattern.contains(['*', '?', '[']) {
+            let paths = glob::glob(&pattern).map_err(|e| {
+                Error::HistoryRead(format!("Invalid history file pattern {}: {}", pattern, e))
+            })?;
+            paths
+                .filter_map(|path| path.ok())
+                .filter(|path| path.is_file())
+                .collect()
+        } else {
+            vec![PathBuf::from(pattern)]
+        };
+
+        Ok(paths
+            .into_iter()
+            .map(|path| {
+                let shell_type = shell_type
+                    .or_else(|| ShellType::from_history_file(&path))
+                    .unwrap_or(fallback);
+                Self::new(path, shell_type)
+            })
+            .collect())
+    }
+
+    /// A short name for the source, shown next to its entries
+    pub fn name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| self.path.display().to_string())
+    }
+}
+
 /// History file formats
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum HistoryFormat {
     /// Plain text format (one command per line)
     Plain,
+    /// Bash format where commands may be preceded by "#epoch" lines (HISTTIMEFORMAT)
+    BashTimestamped,
     /// Zsh extended format (with timestamps and other metadata)
     ZshExtended,
-    /// Fish format (JSON-like)
+    /// Fish format (YAML-like records)
     Fish,
 }
 
@@ -122,20 +230,104 @@ mod tests {
         assert_eq!(ShellType::from_path("/bin/unknown"), None);
     }
 
+    #[test]
+    fn test_shell_type_from_history_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, text: &str| {
+            let path = dir.path().join(name);
+            std::fs::write(&path, text).unwrap();
+            path
+        };
+
+        // The file name wins over the contents
+        assert_eq!(
+            ShellType::from_history_file(&write("zsh", "- cmd: ls\n")),
+            Some(ShellType::Zsh)
+        );
+        assert_eq!(
+            ShellType::from_history_file(&write("a", "- cmd: ls\n  when: 1\n")),
+            Some(ShellType::Fish)
+        );
+        assert_eq!(
+            ShellType::from_history_file(&write("b", ": 1700000000:0;ls\n")),
+            Some(ShellType::Zsh)
+        );
+        assert_eq!(
+            ShellType::from_history_file(&write("c", "#1700000000\nls\n")),
+            Some(ShellType::Bash)
+        );
+        assert_eq!(ShellType::from_history_file(&write("d", "ls -la\n")), None);
+        assert_eq!(
+            ShellType::from_history_file(&write("e", "#comment\n")),
+            None
+        );
+        assert_eq!(
+            ShellType::from_history_file(&dir.path().join("missing")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_expand_history_source() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("one.hist"), ": 1700000000:0;ls\n").unwrap();
+        std::fs::write(dir.path().join("two.hist"), "ls -la\n").unwrap();
+        std::fs::create_dir(dir.path().join("dir.hist")).unwrap();
+        let glob = format!("{}/*.hist", dir.path().display());
+
+        // Without a shell, each file is guessed, falling back to the default;
+        // directories matching the glob are skipped
+        assert_eq!(
+            HistorySource::expand(&glob, None, ShellType::Fish).unwrap(),
+            vec![
+                HistorySource::new(dir.path().join("one.hist"), ShellType::Zsh),
+                HistorySource::new(dir.path().join("two.hist"), ShellType::Fish),
+            ]
+        );
+
+        // A shell given by the user beats the guess, and a prefix beats both
+        assert_eq!(
+            HistorySource::expand(&glob, Some(ShellType::Bash), ShellType::Fish).unwrap(),
+            vec![
+                HistorySource::new(dir.path().join("one.hist"), ShellType::Bash),
+                HistorySource::new(dir.path().join("two.hist"), ShellType::Bash),
+            ]
+        );
+        let prefixed = format!("fish:{}", dir.path().join("one.hist").display());
+        assert_eq!(
+            HistorySource::expand(&prefixed, Some(ShellType::Bash), ShellType::Bash).unwrap(),
+            vec![HistorySource::new(
+                dir.path().join("one.hist"),
+                ShellType::Fish
+            ),]
+        );
+
+        // Plain paths are kept even if missing, while globs may match nothing
+        let home = home_dir().unwrap();
+        assert_eq!(
+            HistorySource::expand("zsh:~/old/history", None, ShellType::Bash).unwrap(),
+            vec![HistorySource::new(home.join("old/history"), ShellType::Zsh),]
+        );
+        let nothing = format!("{}/nothing-*", dir.path().display());
+        assert!(HistorySource::expand(&nothing, None, ShellType::Bash)
+            .unwrap()
+            .is_empty());
+    }
+
     #[test]
     fn test_default_history_path() {
         let home = home_dir().unwrap();
-        
+
         assert_eq!(
             ShellType::Bash.default_history_path().unwrap(),
             home.join(".bash_history")
         );
-        
+
         assert_eq!(
             ShellType::Zsh.default_history_path().unwrap(),
             home.join(".zsh_history")
         );
-        
+
         assert_eq!(
             ShellType::Fish.default_history_path().unwrap(),
             home.join(".local/share/fish/fish_history")
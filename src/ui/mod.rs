@@ -1,3 +1,5 @@
 //! Terminal UI for the ctrl-r application
 
+pub mod theme;
+#[allow(clippy::module_inception)]
 pub mod ui;
@@ -0,0 +1,80 @@
+//! Colors used by the terminal UI
+
+use ratatui::style::{Color, Modifier, Style};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Colors of the picker, configurable in the `[theme]` table
+///
+/// Colors are names such as `cyan` or `lightred`, `#rrggbb` hex values or
+/// 256-color indexes.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Colors used to highlight each dimension's matches, repeating after the last
+    #[serde(deserialize_with = "colors")]
+    pub highlights: Vec<Color>,
+    /// The selected result
+    #[serde(deserialize_with = "color")]
+    pub selected: Color,
+    /// Empty filters and details next to commands
+    #[serde(deserialize_with = "color")]
+    pub muted: Color,
+    /// Invalid patterns
+    #[serde(deserialize_with = "color")]
+    pub error: Color,
+    /// The border of dimensions that exclude their matches
+    #[serde(deserialize_with = "color")]
+    pub exclude: Color,
+    /// The status line
+    #[serde(deserialize_with = "color")]
+    pub status: Color,
+}
+
+impl Theme {
+    /// Style for matches of a dimension
+    pub fn highlight(&self, dimension: usize) -> Style {
+        let style = Style::default().add_modifier(Modifier::BOLD);
+        match self.highlights.len() {
+            0 => style,
+            len => style.fg(self.highlights[dimension % len]),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            highlights: vec![
+                Color::Cyan,
+                Color::Green,
+                Color::Magenta,
+                Color::Blue,
+                Color::Red,
+            ],
+            selected: Color::Yellow,
+            muted: Color::DarkGray,
+            error: Color::Red,
+            exclude: Color::Magenta,
+            status: Color::Gray,
+        }
+    }
+}
+
+/// Deserialize a color from its name, hex value or index
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Color::from_str(&name).map_err(|_| de::Error::custom(format!("invalid color \"{}\"", name)))
+}
+
+/// Deserialize a list of colors
+fn colors<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Color>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|name| {
+            Color::from_str(name)
+                .map_err(|_| de::Error::custom(format!("invalid color \"{}\"", name)))
+        })
+        .collect()
+}
@@ -1,41 +1,156 @@
 //! Terminal UI implementation
 
-use crate::app::App;
+use crate::app::{App, ClickTarget};
+use crate::config::{Height, TimestampMode};
+use crate::history::{Highlight, HistoryEntry};
+use crate::keymap::Action;
+use crate::ui::theme::Theme;
 use crate::Result as AppResult;
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+    cursor::{self, MoveToColumn, MoveToPreviousLine},
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event,
+    },
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    style::Print,
+    terminal::{
+        self as crossterm_terminal, disable_raw_mode, enable_raw_mode, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
 };
 use ratatui::{
     backend::{Backend, CrosstermBackend},
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    widgets::{Block, Borders, List, ListItem, Paragraph},
-    Frame, Terminal,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    Frame, Terminal, TerminalOptions, Viewport,
 };
+use std::collections::HashMap;
 use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use textwrap;
 
+/// How often new history is picked up while it is still loading
+const LOADING_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How the picker was drawn, so the terminal can be restored the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerminalMode {
+    /// On the alternate screen
+    Fullscreen,
+    /// Below the cursor, with the column the cursor was in when it was not at
+    /// the start of a line
+    Inline { prompt_column: Option<u16> },
+}
+
 /// Setup the terminal
-pub fn setup_terminal() -> AppResult<Terminal<CrosstermBackend<io::Stdout>>> {
+///
+/// With a height the picker is drawn below the prompt, scrolling the screen
+/// up if needed, and the output above it stays visible. Otherwise it takes
+/// the alternate screen. Without mouse capture the terminal keeps handling
+/// the mouse itself, so text can still be selected and copied.
+///
+/// If a step fails, the steps before it are undone, so an error never leaves
+/// the shell in raw mode or on the alternate screen.
+pub fn setup_terminal(
+    mouse: bool,
+    height: Option<Height>,
+) -> AppResult<(Terminal<CrosstermBackend<io::Stdout>>, TerminalMode)> {
+    // Ask where the cursor is before anything changes, as a terminal that
+    // never answers makes this fail
+    let mode = match height {
+        Some(_) => {
+            let (column, _) = cursor::position()?;
+            TerminalMode::Inline {
+                prompt_column: (column > 0).then_some(column),
+            }
+        }
+        None => TerminalMode::Fullscreen,
+    };
+
     enable_raw_mode()?;
+    let terminal = enter_terminal(mouse, height, mode).inspect_err(|_| reset_terminal(mode))?;
+    Ok((terminal, mode))
+}
+
+/// Switch to the picker's screen once raw mode is on
+fn enter_terminal(
+    mouse: bool,
+    height: Option<Height>,
+    mode: TerminalMode,
+) -> AppResult<Terminal<CrosstermBackend<io::Stdout>>> {
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    match mode {
+        // Keep the line the cursor is on, usually the prompt, unless it is empty
+        TerminalMode::Inline {
+            prompt_column: Some(_),
+        } => execute!(stdout, Print("\r\n"))?,
+        TerminalMode::Inline {
+            prompt_column: None,
+        } => {}
+        TerminalMode::Fullscreen => execute!(stdout, EnterAlternateScreen)?,
+    }
+    execute!(stdout, EnableBracketedPaste)?;
+    if mouse {
+        execute!(stdout, EnableMouseCapture)?;
+    }
+
     let backend = CrosstermBackend::new(stdout);
-    let terminal = Terminal::new(backend)?;
+    let terminal = match height {
+        Some(height) => {
+            let (_, rows) = crossterm_terminal::size()?;
+            let viewport = Viewport::Inline(height.rows(rows));
+            Terminal::with_options(backend, TerminalOptions { viewport })?
+        }
+        None => Terminal::new(backend)?,
+    };
     Ok(terminal)
 }
 
+/// Undo whatever part of the setup ran, ignoring errors as the terminal may
+/// be the reason the setup failed
+fn reset_terminal(mode: TerminalMode) {
+    let mut stdout = io::stdout();
+    let _ = execute!(stdout, DisableMouseCapture, DisableBracketedPaste);
+    if mode == TerminalMode::Fullscreen {
+        let _ = execute!(stdout, LeaveAlternateScreen);
+    }
+    let _ = disable_raw_mode();
+}
+
 /// Restore the terminal
-pub fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> AppResult<()> {
-    disable_raw_mode()?;
-    execute!(
+///
+/// An inline picker is erased and the cursor put back where it was before,
+/// leaving the output above untouched. Every step is tried even if one
+/// fails, and the first error is returned.
+pub fn restore_terminal(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    mode: TerminalMode,
+) -> AppResult<()> {
+    let raw_mode = disable_raw_mode();
+    let screen = match mode {
+        TerminalMode::Fullscreen => execute!(terminal.backend_mut(), LeaveAlternateScreen),
+        TerminalMode::Inline { prompt_column } => {
+            terminal.clear().and_then(|()| match prompt_column {
+                Some(column) => execute!(
+                    terminal.backend_mut(),
+                    MoveToPreviousLine(1),
+                    MoveToColumn(column)
+                ),
+                None => Ok(()),
+            })
+        }
+    };
+    let input = execute!(
         terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+        DisableMouseCapture,
+        DisableBracketedPaste
+    );
+    let cursor = terminal.show_cursor();
+
+    raw_mode.and(screen).and(input).and(cursor)?;
     Ok(())
 }
 
@@ -45,14 +160,25 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> AppResu
     app.update_filters()?;
 
     loop {
+        app.poll_history()?;
+
         terminal.draw(|f| ui(f, app))?;
 
         if app.should_quit() {
             break;
         }
 
-        if let Event::Key(key) = event::read()? {
-            app.handle_key_event(key)?;
+        // Keep picking up history while it loads. Keys typed meanwhile stay
+        // queued by the terminal until they are read here
+        if app.is_loading() && !event::poll(LOADING_POLL_INTERVAL)? {
+            continue;
+        }
+
+        match event::read()? {
+            Event::Key(key) => app.handle_key_event(key)?,
+            Event::Paste(text) => app.handle_paste(&text)?,
+            Event::Mouse(mouse) => app.handle_mouse_event(mouse)?,
+            _ => {}
         }
     }
 
@@ -60,133 +186,1038 @@ pub fn run_app<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> AppResu
 }
 
 /// Render the UI
-pub fn ui(f: &mut Frame, app: &App) {
-    // Create constraints for all possible filters (up to max_dimensions)
-    let mut constraints = Vec::with_capacity(app.max_dimensions() + 2); // +2 for results and status line
-    
-    // Add constraints for each filter dimension
-    for _ in 0..app.max_dimensions() {
-        constraints.push(Constraint::Length(3));
-    }
-    
-    // Add constraints for results and status line
-    constraints.push(Constraint::Min(1));     // Results
-    constraints.push(Constraint::Length(1));  // Status line
-    
+pub fn ui(f: &mut Frame, app: &mut App) {
+    let theme = &app.config().theme;
+
+    // Dimensions after the current one are only shown while they hold a filter
+    let visible: Vec<usize> = (0..app.dimension_count())
+        .filter(|&dim| dim <= app.current_dimension() || !app.filter(dim).is_empty())
+        .collect();
+
+    // Fall back to more compact filters when the terminal is too short for
+    // a box per dimension. Two rows go to the margin and one to the status line
+    let filter_layout = FilterLayout::choose(f.size().height.saturating_sub(3), visible.len());
+    let mut constraints = filter_layout.constraints(&visible, app.current_dimension());
+    constraints.push(Constraint::Min(1)); // Results
+    constraints.push(Constraint::Length(1)); // Status line
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints(constraints)
         .split(f.size());
 
-    // Render each filter dimension
-    for dim in 0..app.max_dimensions() {
-        let is_active = dim == app.current_dimension();
-        let has_content = !app.filter(dim).is_empty();
-        
-        // Skip rendering dimensions beyond the current one if they're empty
-        if dim > app.current_dimension() && !has_content {
-            continue;
+    // Render the filters, remembering where the cursor goes in the active one
+    // and where each one can be clicked
+    let mut click_targets = Vec::new();
+    let cursor = match filter_layout {
+        FilterLayout::Chips => render_filter_chips(f, app, &visible, chunks[0], &mut click_targets),
+        _ => {
+            let mut cursor = (0, 0);
+            for (&dim, &area) in visible.iter().zip(chunks.iter()) {
+                click_targets.push((area, ClickTarget::Dimension(dim)));
+                if dim == app.current_dimension() {
+                    let line = filter_line(app, dim);
+                    f.render_widget(filter_box(app, dim, line), area);
+                    let before_cursor = text_width(&app.filter(dim)[..app.cursor(dim)]);
+                    cursor = (
+                        (area.x + before_cursor + 1).min(area.right().saturating_sub(2)),
+                        area.y + 1,
+                    );
+                } else if filter_layout == FilterLayout::Collapsed {
+                    f.render_widget(Paragraph::new(filter_row(app, dim)), area);
+                } else {
+                    f.render_widget(filter_box(app, dim, filter_line(app, dim)), area);
+                }
+            }
+            cursor
         }
-        
-        // Create dimension name
-        let dimension_name = match dim {
-            0 => "1st".to_string(),
-            1 => "2nd".to_string(),
-            2 => "3rd".to_string(),
-            _ => format!("{}th", dim + 1),
-        };
-        
-        // Create filter title
-        let filter_title = if is_active {
-            format!("Filter ({} dimension) [active]", dimension_name)
+    };
+
+    // Results (positioned after the filters), sharing their space with the
+    // preview when it is shown
+    let results_idx = chunks.len() - 2;
+    let (area, preview_area) = if app.show_preview() {
+        let (results_area, preview_area) = split_preview(chunks[results_idx]);
+        (results_area, Some(preview_area))
+    } else {
+        (chunks[results_idx], None)
+    };
+
+    // Calculate the available width for wrapping, leaving room for the
+    // timestamp column when it is shown
+    let timestamp_mode = app.timestamp_mode();
+    let column_width = timestamp_column_width(timestamp_mode);
+    let available_width = (area.width.saturating_sub(4) as usize)
+        .saturating_sub(column_width)
+        .max(1);
+    let now = now();
+
+    // Only the results that can be in view are wrapped, so a key press costs
+    // the same however many entries match
+    let count = app.filtered_entries().len();
+    let inner_height = area.height.saturating_sub(2) as usize;
+    let selected_index = app.selected_index();
+    let mut items = HashMap::new();
+    let mut item_height = |index| {
+        items
+            .entry(index)
+            .or_insert_with(|| result_item(app, index, available_width, timestamp_mode, now))
+            .height()
+    };
+    let offset = scroll_offset(
+        app.results_state().offset(),
+        selected_index,
+        count,
+        inner_height,
+        &mut item_height,
+    );
+
+    // Take the items from the offset until the view is full, remembering
+    // how many rows each takes
+    let mut item_heights = Vec::new();
+    let mut results_items = Vec::new();
+    let mut rows = 0;
+    for index in offset..count {
+        if rows >= inner_height {
+            break;
+        }
+        let item = items
+            .remove(&index)
+            .unwrap_or_else(|| result_item(app, index, available_width, timestamp_mode, now));
+        rows += item.height();
+        item_heights.push(item.height());
+        results_items.push(item);
+    }
+
+    // Create the results list with automatic text wrapping
+    let results_count = format!(
+        "{} results ({}, {}){}",
+        count,
+        app.match_mode().name(),
+        app.rank_mode().name(),
+        if app.is_loading() { " loading..." } else { "" },
+    );
+    let results_list =
+        List::new(results_items).block(Block::default().borders(Borders::ALL).title(results_count));
+
+    // Status line (positioned after results)
+    let status = status_hints(app);
+
+    let status_line = Paragraph::new(status)
+        .style(Style::default().fg(theme.status))
+        .block(Block::default());
+    f.render_widget(status_line, chunks[results_idx + 1]);
+
+    f.set_cursor(cursor.0, cursor.1);
+
+    if let Some(preview_area) = preview_area {
+        let entry = app
+            .filtered_entries()
+            .get(app.selected_index())
+            .map(|filtered| &filtered.entry);
+        let lines = entry
+            .map(|entry| preview_lines(app, entry, now))
+            .unwrap_or_default();
+        let preview = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .block(Block::default().borders(Borders::ALL).title("Preview"));
+        f.render_widget(preview, preview_area);
+    }
+
+    // Render the list last as it needs the list state mutably. The state
+    // keeps the offset into all results, while the list only holds the
+    // items in view
+    let state = app.results_state_mut();
+    *state = ListState::default()
+        .with_offset(offset)
+        .with_selected(Some(selected_index));
+    let mut window_state =
+        ListState::default().with_selected(Some(selected_index.saturating_sub(offset)));
+    f.render_stateful_widget(results_list, area, &mut window_state);
+
+    // Note the rows of the items that fit from the offset, for paging and clicks
+    let mut row = 0;
+    let mut page_size = 0;
+    for (index, &height) in (offset..).zip(&item_heights) {
+        if row + height > inner_height {
+            break;
+        }
+        let item_area = Rect::new(
+            area.x + 1,
+            area.y + 1 + row as u16,
+            area.width.saturating_sub(2),
+            height as u16,
+        );
+        click_targets.push((item_area, ClickTarget::Result(index)));
+        row += height;
+        page_size += 1;
+    }
+    app.set_page_size(page_size.max(1));
+    app.set_click_targets(click_targets);
+}
+
+/// Build the list item of one result, wrapped to `available_width` columns
+fn result_item<'a>(
+    app: &App,
+    index: usize,
+    available_width: usize,
+    timestamp_mode: TimestampMode,
+    now: u64,
+) -> ListItem<'a> {
+    let theme = &app.config().theme;
+    let filtered = &app.filtered_entries()[index];
+    let column_width = timestamp_column_width(timestamp_mode);
+    let entry = &filtered.entry;
+
+    // Wrap the command text to fit within the available width
+    let mut lines = wrap_command(&entry.command, &filtered.highlights, available_width, theme);
+
+    // Show how often and how long the command ran and which history
+    // file it came from, on the last line if it still fits
+    let mut details = Vec::new();
+    if entry.count > 1 {
+        details.push(format!(" ×{}", entry.count));
+    }
+    if let Some(duration) = entry.duration.filter(|d| *d > 0) {
+        details.push(format!(" ({})", format_duration(duration)));
+    }
+    if let Some(source) = app.entry_source(entry) {
+        details.push(format!(" [{}]", source.name()));
+    }
+    for detail in details {
+        let detail_span = Span::styled(detail, Style::default().fg(theme.muted));
+        match lines.last_mut() {
+            Some(last) if last.width() + detail_span.width() <= available_width => {
+                last.spans.push(detail_span);
+            }
+            _ => lines.push(Line::from(detail_span)),
+        }
+    }
+
+    // Put the timestamp before the first line and indent the others
+    // to keep wrapped commands lined up
+    if column_width > 0 {
+        let stamp = timestamp_column(timestamp_mode, entry.timestamp, now);
+        for (row, line) in lines.iter_mut().enumerate() {
+            let prefix = if row == 0 {
+                stamp.clone()
+            } else {
+                " ".repeat(column_width)
+            };
+            line.spans
+                .insert(0, Span::styled(prefix, Style::default().fg(theme.muted)));
+        }
+    }
+
+    // Style the selected item as a base, so match highlights keep
+    // their colors on top of it
+    let style = if index == app.selected_index() {
+        Style::default()
+            .fg(theme.selected)
+            .add_modifier(Modifier::BOLD)
+    } else {
+        Style::default()
+    };
+
+    ListItem::new(lines).style(style)
+}
+
+/// Narrowest results area that puts the preview beside the results rather
+/// than below them
+const PREVIEW_BESIDE_MIN_WIDTH: u16 = 100;
+
+/// Split the results area into the results and the preview
+///
+/// Wide terminals get the preview on the right, others below the results.
+fn split_preview(area: Rect) -> (Rect, Rect) {
+    let (direction, constraints) = if area.width >= PREVIEW_BESIDE_MIN_WIDTH {
+        (
+            Direction::Horizontal,
+            [Constraint::Percentage(55), Constraint::Percentage(45)],
+        )
+    } else {
+        (
+            Direction::Vertical,
+            [Constraint::Percentage(60), Constraint::Percentage(40)],
+        )
+    };
+    let chunks = Layout::default()
+        .direction(direction)
+        .constraints(constraints)
+        .split(area);
+    (chunks[0], chunks[1])
+}
+
+/// Build the preview of an entry: the full command as it will be run,
+/// followed by what is known about it
+fn preview_lines<'a>(app: &App, entry: &HistoryEntry, now: u64) -> Vec<Line<'a>> {
+    let theme = &app.config().theme;
+    let mut lines: Vec<Line> = entry
+        .full_command
+        .lines()
+        .map(|line| Line::from(line.to_string()))
+        .collect();
+
+    let mut details = Vec::new();
+    if let Some(timestamp) = entry.timestamp {
+        details.push((
+            "Last run",
+            format!(
+                "{} UTC ({} ago)",
+                format_date(timestamp),
+                format_age(now, timestamp)
+            ),
+        ));
+    }
+    if let Some(first_seen) = entry
+        .first_seen
+        .filter(|first_seen| Some(*first_seen) != entry.timestamp)
+    {
+        details.push((
+            "First run",
+            format!(
+                "{} UTC ({} ago)",
+                format_date(first_seen),
+                format_age(now, first_seen)
+            ),
+        ));
+    }
+    if let Some(duration) = entry.duration {
+        details.push(("Duration", format_duration(duration)));
+    }
+    details.push(("Runs", entry.count.to_string()));
+    if let Some(source) = app.entry_source(entry) {
+        details.push(("Source", source.path.display().to_string()));
+    }
+    if !entry.paths.is_empty() {
+        details.push(("Paths", entry.paths.join(", ")));
+    }
+    if shows_original(entry) {
+        details.push(("Original", entry.original_line.clone()));
+    }
+
+    lines.push(Line::default());
+    for (label, value) in details {
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:<10} ", label), Style::default().fg(theme.muted)),
+            Span::raw(value),
+        ]));
+    }
+    lines
+}
+
+/// Whether the original history record says more than the rest of the preview
+///
+/// Zsh's `: start:elapsed;` prefix and bash's `#start` line only repeat the
+/// times shown above, so a record that is just those and the command is left
+/// out.
+fn shows_original(entry: &HistoryEntry) -> bool {
+    let original = entry.original_line.as_str();
+    if original.is_empty() || original == entry.full_command {
+        return false;
+    }
+
+    let Some(prefix) = original.strip_suffix(entry.full_command.as_str()) else {
+        return true;
+    };
+    let is_number = |time: &str| !time.is_empty() && time.chars().all(|c| c.is_ascii_digit());
+    let is_zsh_prefix = prefix
+        .strip_prefix(": ")
+        .and_then(|prefix| prefix.strip_suffix(';'))
+        .is_some_and(|times| times.split(':').all(is_number));
+    let is_bash_prefix = prefix
+        .strip_prefix('#')
+        .and_then(|prefix| prefix.strip_suffix('\n'))
+        .is_some_and(is_number);
+    !is_zsh_prefix && !is_bash_prefix
+}
+
+/// Get the first item to show so the selected item is fully visible
+///
+/// The list only scrolls when the selection would leave the view, and then
+/// only as far as needed, taking the rows of wrapped items into account.
+/// Only the items between the selection and the new offset are measured.
+fn scroll_offset(
+    offset: usize,
+    selected: usize,
+    count: usize,
+    height: usize,
+    mut item_height: impl FnMut(usize) -> usize,
+) -> usize {
+    if selected >= count {
+        return 0;
+    }
+    if selected < offset {
+        return selected;
+    }
+
+    // Walk up from the selection while the items still fit
+    let mut first = selected;
+    let mut rows = item_height(selected);
+    while first > offset {
+        let above = item_height(first - 1);
+        if rows + above > height {
+            break;
+        }
+        rows += above;
+        first -= 1;
+    }
+    first
+}
+
+/// Height of a filter box, including its borders
+const FILTER_BOX_HEIGHT: u16 = 3;
+
+/// Rows kept for the results list, including its borders, before the
+/// filters are made more compact
+const MIN_RESULTS_HEIGHT: u16 = 5;
+
+/// How the filter dimensions are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FilterLayout {
+    /// Every dimension in its own box
+    Boxes,
+    /// The active dimension in a box and the others on one row each
+    Collapsed,
+    /// Every dimension as a chip on a single row
+    Chips,
+}
+
+impl FilterLayout {
+    /// Choose the roomiest layout that leaves the results list enough rows
+    ///
+    /// `height` is the number of rows shared by the filters and the results.
+    fn choose(height: u16, visible: usize) -> Self {
+        let visible = visible as u16;
+        if visible * FILTER_BOX_HEIGHT + MIN_RESULTS_HEIGHT <= height {
+            Self::Boxes
+        } else if FILTER_BOX_HEIGHT + visible.saturating_sub(1) + MIN_RESULTS_HEIGHT <= height {
+            Self::Collapsed
         } else {
-            format!("Filter ({} dimension)", dimension_name)
-        };
+            Self::Chips
+        }
+    }
+
+    /// Constraints for the rows of the visible dimensions
+    fn constraints(&self, visible: &[usize], current_dimension: usize) -> Vec<Constraint> {
+        match self {
+            Self::Boxes => vec![Constraint::Length(FILTER_BOX_HEIGHT); visible.len()],
+            Self::Collapsed => visible
+                .iter()
+                .map(|&dim| match dim == current_dimension {
+                    true => Constraint::Length(FILTER_BOX_HEIGHT),
+                    false => Constraint::Length(1),
+                })
+                .collect(),
+            Self::Chips => vec![Constraint::Length(1)],
+        }
+    }
+}
 
-        // Set filter style
-        let filter_style = if is_active || has_content {
-            Style::default()
+/// Get the ordinal of a 0-based dimension, such as "1st" or "12th"
+fn ordinal(dimension: usize) -> String {
+    let n = dimension + 1;
+    let suffix = match (n % 10, n % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", n, suffix)
+}
+
+/// The text of a filter, followed by its pattern error if any
+fn filter_line<'a>(app: &'a App, dimension: usize) -> Line<'a> {
+    let theme = &app.config().theme;
+    let mut line = Line::from(app.filter(dimension));
+    if let Some(message) = app.pattern_error(dimension) {
+        line.spans.push(Span::styled(
+            format!("  {}", message),
+            Style::default().fg(theme.error),
+        ));
+    }
+    line
+}
+
+/// A filter dimension drawn as a box with a title
+fn filter_box<'a>(app: &'a App, dimension: usize, line: Line<'a>) -> Paragraph<'a> {
+    let theme = &app.config().theme;
+    let is_active = dimension == app.current_dimension();
+    let has_content = !app.filter(dimension).is_empty();
+
+    // Create filter title, including how this dimension matches
+    let dimension_filter = app.dimension_filter(dimension);
+    let mode_label = format!("[{}]", dimension_filter.describe(app.match_mode()));
+    let filter_title = if is_active {
+        format!(
+            "Filter ({} dimension) {} [active]",
+            ordinal(dimension),
+            mode_label
+        )
+    } else {
+        format!("Filter ({} dimension) {}", ordinal(dimension), mode_label)
+    };
+
+    // Set filter style
+    let filter_style = if is_active || has_content {
+        Style::default()
+    } else {
+        Style::default().fg(theme.muted)
+    };
+
+    // Color the title like this dimension's matches in the results
+    let filter_title = Span::styled(filter_title, theme.highlight(dimension));
+
+    // Create filter block with title, highlighted when the pattern is invalid
+    // and tinted when the dimension excludes its matches
+    let mut filter_block = Block::default().borders(Borders::ALL).title(filter_title);
+    if app.pattern_error(dimension).is_some() {
+        filter_block = filter_block.border_style(Style::default().fg(theme.error));
+    } else if dimension_filter.is_negated() {
+        filter_block = filter_block.border_style(Style::default().fg(theme.exclude));
+    }
+
+    Paragraph::new(line).style(filter_style).block(filter_block)
+}
+
+/// An inactive filter dimension collapsed to a single row
+fn filter_row<'a>(app: &'a App, dimension: usize) -> Line<'a> {
+    let theme = &app.config().theme;
+    let label = format!(
+        " {} [{}] ",
+        ordinal(dimension),
+        app.dimension_filter(dimension).describe(app.match_mode())
+    );
+    let mut line = filter_line(app, dimension);
+    line.spans
+        .insert(0, Span::styled(label, theme.highlight(dimension)));
+    line
+}
+
+/// Draw every visible dimension as a chip on one row, returning where the
+/// cursor goes
+///
+/// When the chips are wider than the row, the leading ones are dropped so
+/// the active dimension stays in view.
+fn render_filter_chips(
+    f: &mut Frame,
+    app: &App,
+    visible: &[usize],
+    area: Rect,
+    click_targets: &mut Vec<(Rect, ClickTarget)>,
+) -> (u16, u16) {
+    let theme = &app.config().theme;
+    let chip = |dim: usize| {
+        let mut style = theme.highlight(dim);
+        if app.pattern_error(dim).is_some() {
+            style = style.fg(theme.error);
+        } else if app.dimension_filter(dim).is_negated() {
+            style = style.fg(theme.exclude);
+        }
+        let label = format!("{}:", dim + 1);
+        let text = app.filter(dim);
+        if dim == app.current_dimension() {
+            // Split at the cursor so it can be placed between the two halves
+            let (before, after) = text.split_at(app.cursor(dim));
+            vec![
+                Span::styled(label, style.add_modifier(Modifier::REVERSED)),
+                Span::styled(
+                    before.to_string(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::styled(
+                    after.to_string(),
+                    Style::default().add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" "),
+            ]
         } else {
-            Style::default().fg(Color::DarkGray)
-        };
+            vec![
+                Span::styled(label, style),
+                Span::raw(text.to_string()),
+                Span::raw(" "),
+            ]
+        }
+    };
 
-        // Create filter block with title
-        let filter_block = Block::default()
-            .borders(Borders::ALL)
-            .title(filter_title);
-
-        // Create and render filter paragraph
-        let filter_text = app.filter(dim);
-        let filter = Paragraph::new(filter_text)
-            .style(filter_style)
-            .block(filter_block);
-            
-        f.render_widget(filter, chunks[dim]);
-    }
-
-    // Results (positioned after all filter dimensions)
-    let results_idx = app.max_dimensions();
-    
-    // Calculate the available width for wrapping
-    let available_width = chunks[results_idx].width.saturating_sub(4) as usize;
-    
-    // Create a list of items for each entry
-    let results_items: Vec<ListItem> = app
-        .filtered_entries()
+    let chips: Vec<Vec<Span>> = visible.iter().map(|&dim| chip(dim)).collect();
+    let width = |chip: &[Span]| chip.iter().map(Span::width).sum::<usize>();
+    let active = visible
         .iter()
-        .enumerate()
-        .map(|(i, entry)| {
-            // Wrap the command text to fit within the available width
-            // Use textwrap with options to better handle the specific requirements
-            let mut options = textwrap::Options::new(available_width);
-            options.break_words = true;  // Allow breaking words if they're too long
-            options.word_separator = textwrap::WordSeparator::AsciiSpace;  // Use ASCII space as word separator
-            
-            let wrapped_text = textwrap::fill(&entry.command, options);
-            
-            // Apply style based on selection
-            let style = if i == app.selected_index() {
-                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
-            } else {
-                Style::default()
-            };
-            
-            // Create a ListItem with the wrapped command text and style
-            ListItem::new(wrapped_text).style(style)
+        .position(|&dim| dim == app.current_dimension())
+        .unwrap_or(0);
+
+    // Drop chips from the front until the active one fits
+    let mut first = 0;
+    while first < active
+        && chips[first..=active]
+            .iter()
+            .map(|c| width(c))
+            .sum::<usize>()
+            + 2
+            > area.width as usize
+    {
+        first += 1;
+    }
+
+    let mut spans = Vec::new();
+    if first > 0 {
+        spans.push(Span::styled("… ", Style::default().fg(theme.muted)));
+    }
+    let mut cursor_x = area.x;
+    for (index, chip) in chips.into_iter().enumerate().skip(first) {
+        let offset = spans.iter().map(Span::width).sum::<usize>();
+        if index == active {
+            // The cursor goes after the label and the text before the cursor
+            cursor_x = area.x + (offset + width(&chip[..2])) as u16;
+        }
+        let chip_area =
+            Rect::new(area.x + offset as u16, area.y, width(&chip) as u16, 1).intersection(area);
+        click_targets.push((chip_area, ClickTarget::Dimension(visible[index])));
+        spans.extend(chip);
+    }
+    if let Some(message) = app.pattern_error(app.current_dimension()) {
+        spans.push(Span::styled(
+            format!(" {}", message),
+            Style::default().fg(theme.error),
+        ));
+    }
+
+    f.render_widget(Paragraph::new(Line::from(spans)), area);
+    (cursor_x.min(area.right().saturating_sub(1)), area.y)
+}
+
+/// Get the number of columns a string takes up on screen
+fn text_width(text: &str) -> u16 {
+    Span::raw(text).width() as u16
+}
+
+/// Describe the key bindings on the status line
+fn status_hints(app: &App) -> String {
+    let keymap = &app.config().keymap;
+    let key = |action| {
+        keymap
+            .key_for(app.edit_mode(), action)
+            .map(|key| key.to_string())
+    };
+    let mut hints = Vec::new();
+
+    if keymap.is_modal() {
+        hints.push(format!("-- {} --", app.edit_mode().name()));
+    }
+
+    if app.can_add_dimension() {
+        if let Some(key) = key(Action::NextDimension) {
+            hints.push(format!(
+                "Press {} to add a {} dimension filter",
+                key,
+                ordinal(app.current_dimension() + 1)
+            ));
+        }
+    }
+    if let (Some(up), Some(down)) = (key(Action::SelectPrevious), key(Action::SelectNext)) {
+        hints.push(format!("{}/{} to navigate", up, down));
+    }
+    for action in [
+        Action::ToggleFuzzy,
+        Action::ToggleRank,
+        Action::ToggleRegex,
+        Action::ToggleExclude,
+        Action::CycleCase,
+        Action::ToggleWord,
+        Action::TogglePreview,
+        Action::CycleTimestamps,
+    ] {
+        if let Some(key) = key(action) {
+            hints.push(format!("{} {}", key, action.label()));
+        }
+    }
+    if let Some(key) = key(Action::Accept) {
+        hints.push(format!("{} to select", key));
+    }
+    for action in [Action::NormalMode, Action::InsertMode] {
+        if let Some(key) = key(action) {
+            hints.push(format!("{} {}", key, action.label()));
+        }
+    }
+    if let Some(key) = key(Action::Quit) {
+        hints.push(format!("{} to cancel", key));
+    }
+
+    hints.join(" | ")
+}
+
+/// Wrap a command to `width` columns, carrying the match highlights across
+/// the line breaks
+fn wrap_command<'a>(
+    command: &str,
+    highlights: &[Highlight],
+    width: usize,
+    theme: &Theme,
+) -> Vec<Line<'a>> {
+    let mut options = textwrap::Options::new(width);
+    options.break_words = true; // Allow breaking words if they're too long
+    options.word_separator = textwrap::WordSeparator::AsciiSpace; // Use ASCII space as word separator
+
+    textwrap::wrap(command, options)
+        .into_iter()
+        .map(|line| match line_offset(command, &line) {
+            Some(start) => highlighted_line(&line, start, highlights, theme),
+            // A line textwrap had to rebuild can't be matched up with the
+            // highlights, so it is shown plain
+            None => Line::from(line.into_owned()),
         })
+        .collect()
+}
+
+/// Byte offset of a wrapped line within the text it was wrapped from, if the
+/// line borrows from that text rather than being rebuilt
+fn line_offset(text: &str, line: &str) -> Option<usize> {
+    let start = (line.as_ptr() as usize).checked_sub(text.as_ptr() as usize)?;
+    (start + line.len() <= text.len()).then_some(start)
+}
+
+/// Build a line of a wrapped command, coloring the highlighted ranges
+///
+/// `start` is the byte offset of `text` within the full command. Where
+/// highlights overlap, the one that starts first keeps the overlapping part.
+fn highlighted_line<'a>(
+    text: &str,
+    start: usize,
+    highlights: &[Highlight],
+    theme: &Theme,
+) -> Line<'a> {
+    let end = start + text.len();
+    let mut ranges: Vec<&Highlight> = highlights
+        .iter()
+        .filter(|h| h.range.start < end && h.range.end > start)
         .collect();
+    ranges.sort_by_key(|h| h.range.start);
 
-    // Create the results list with automatic text wrapping
-    let results_count = format!("{} results", results_items.len());
-    let results_list = List::new(results_items)
-        .block(Block::default().borders(Borders::ALL).title(results_count));
-    
-    // Render the list with wrapping enabled
-    let area = chunks[results_idx];
-    f.render_widget(results_list, area);
+    let mut spans = Vec::new();
+    let mut cursor = start;
+    for highlight in ranges {
+        let from = highlight.range.start.max(cursor);
+        let to = highlight.range.end.min(end);
+        if from >= to {
+            continue;
+        }
+        if from > cursor {
+            spans.push(Span::raw(text[cursor - start..from - start].to_string()));
+        }
+        spans.push(Span::styled(
+            text[from - start..to - start].to_string(),
+            theme.highlight(highlight.dimension),
+        ));
+        cursor = to;
+    }
+    if cursor < end {
+        spans.push(Span::raw(text[cursor - start..].to_string()));
+    }
 
-    // Status line (positioned after results)
-    let status_idx = app.max_dimensions() + 1;
-    let can_add_dimension = app.current_dimension() < app.max_dimensions() - 1 && 
-                           !app.filter(app.current_dimension()).is_empty();
-    
-    let status = if can_add_dimension {
-        format!("Press Ctrl+R to add a {}th dimension filter | Up/Down to navigate | Enter to select | Esc to cancel", 
-                app.current_dimension() + 2)
+    Line::from(spans)
+}
+
+/// Format a duration in seconds as a short human-readable string
+fn format_duration(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{:02}m", seconds / 3600, (seconds % 3600) / 60),
+    }
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
+
+/// Format how long ago a timestamp was, such as `3m`, `2d` or `5w`
+fn format_age(now: u64, timestamp: u64) -> String {
+    let age = now.saturating_sub(timestamp);
+    match age {
+        0..=59 => format!("{}s", age),
+        60..=3599 => format!("{}m", age / 60),
+        3600..=86_399 => format!("{}h", age / 3600),
+        86_400..=604_799 => format!("{}d", age / 86_400),
+        604_800..=31_535_999 => format!("{}w", age / 604_800),
+        _ => format!("{}y", age / 31_536_000),
+    }
+}
+
+/// Width of the timestamp column, including the space after it
+fn timestamp_column_width(mode: TimestampMode) -> usize {
+    match mode {
+        TimestampMode::None => 0,
+        // Ages are at most three characters, such as `59m` or `52w`
+        TimestampMode::Relative => 4,
+        TimestampMode::Absolute => 11,
+    }
+}
+
+/// The timestamp column of an entry, padded to the column width and blank
+/// when the history has no timestamps
+fn timestamp_column(mode: TimestampMode, timestamp: Option<u64>, now: u64) -> String {
+    let width = timestamp_column_width(mode).saturating_sub(1);
+    let text = match (mode, timestamp) {
+        (TimestampMode::Relative, Some(timestamp)) => format_age(now, timestamp),
+        (TimestampMode::Absolute, Some(timestamp)) => format_day(timestamp),
+        _ => String::new(),
+    };
+    format!("{:>width$} ", text, width = width)
+}
+
+/// Format a timestamp as a UTC date and time, such as `2024-03-05 14:02`
+fn format_date(timestamp: u64) -> String {
+    let seconds = timestamp % 86_400;
+    format!(
+        "{} {:02}:{:02}",
+        format_day(timestamp),
+        seconds / 3600,
+        (seconds % 3600) / 60
+    )
+}
+
+/// Format the UTC day of a timestamp, such as `2024-03-05`
+fn format_day(timestamp: u64) -> String {
+    let days = timestamp / 86_400;
+
+    // Convert days since the epoch to a civil date, after Howard Hinnant's
+    // days_from_civil inverse, counting from 0000-03-01
+    let days = days as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
     } else {
-        "Up/Down to navigate | Enter to select | Esc to cancel".to_string()
+        month_index - 9
     };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
 
-    let status_line = Paragraph::new(status)
-        .style(Style::default().fg(Color::Gray))
-        .block(Block::default());
-    f.render_widget(status_line, chunks[status_idx]);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
 
-    // Set cursor position at the end of the current filter
-    let current_dim = app.current_dimension();
-    f.set_cursor(
-        chunks[current_dim].x + app.filter(current_dim).len() as u16 + 1,
-        chunks[current_dim].y + 1,
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+    use ratatui::backend::TestBackend;
+
+    /// Draw the picker into a buffer and return its rows
+    fn draw(terminal: &mut Terminal<TestBackend>, app: &mut App) -> Vec<String> {
+        terminal.draw(|f| ui(f, app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer.get(x, y).symbol.as_str())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_results_window_follows_selection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bash_history");
+        let commands: Vec<String> = (0..1000).map(|i| format!("echo {}", i)).collect();
+        std::fs::write(&path, commands.join("\n") + "\n").unwrap();
+        let mut config =
+            Config::new(Some("bash".to_string()), vec![path.display().to_string()]).unwrap();
+        config.cache_dir = None;
+        let mut app = App::new(config).unwrap();
+        while app.is_loading() {
+            app.poll_history().unwrap();
+        }
+        app.update_filters().unwrap();
+
+        let mut terminal = Terminal::new(TestBackend::new(40, 12)).unwrap();
+        let rows = draw(&mut terminal, &mut app);
+        assert!(rows.iter().any(|row| row.contains("1000 results")));
+        assert!(rows.iter().any(|row| row.contains("echo 0 ")));
+
+        // Only the items around the selection are built, but the last one is
+        // still drawn with the ones before it
+        let end = KeyEvent::new(KeyCode::End, KeyModifiers::NONE);
+        app.handle_key_event(end).unwrap();
+        let rows = draw(&mut terminal, &mut app);
+        assert!(rows.iter().any(|row| row.contains("echo 999")));
+        assert!(rows.iter().any(|row| row.contains("echo 998")));
+        assert!(!rows.iter().any(|row| row.contains("echo 0 ")));
+        let shown = rows.iter().filter(|row| row.contains("echo ")).count();
+        assert_eq!(app.results_state().offset(), 1000 - shown);
+    }
+
+    #[test]
+    fn test_filter_layout_fits_height() {
+        // Three boxes and the results list fit in 14 rows
+        assert_eq!(FilterLayout::choose(14, 3), FilterLayout::Boxes);
+        // One box and two collapsed rows fit in 10
+        assert_eq!(FilterLayout::choose(10, 3), FilterLayout::Collapsed);
+        assert_eq!(FilterLayout::choose(9, 3), FilterLayout::Chips);
+        assert_eq!(FilterLayout::choose(4, 1), FilterLayout::Chips);
+
+        let constraints = FilterLayout::Collapsed.constraints(&[0, 1, 2], 1);
+        assert_eq!(
+            constraints,
+            vec![
+                Constraint::Length(1),
+                Constraint::Length(3),
+                Constraint::Length(1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scroll_offset_keeps_selection_visible() {
+        let heights = [1, 3, 2, 1, 4, 1];
+        let scroll_offset = |offset, selected, heights: &[usize], height| {
+            scroll_offset(offset, selected, heights.len(), height, |i| heights[i])
+        };
+
+        // Nothing moves while the selection stays in view
+        assert_eq!(scroll_offset(0, 2, &heights, 6), 0);
+        // Moving below the view scrolls until the selected item fits
+        assert_eq!(scroll_offset(0, 3, &heights, 6), 1);
+        assert_eq!(scroll_offset(1, 4, &heights, 6), 3);
+        // Items taller than the view are shown from their first row
+        assert_eq!(scroll_offset(0, 4, &heights, 2), 4);
+        // Moving above the view scrolls up to the selection
+        assert_eq!(scroll_offset(4, 2, &heights, 6), 2);
+        // An offset from a longer list is reset
+        assert_eq!(scroll_offset(3, 0, &[], 6), 0);
+    }
+
+    #[test]
+    fn test_split_preview() {
+        let (results, preview) = split_preview(Rect::new(0, 0, 120, 30));
+        assert_eq!((results.height, preview.height), (30, 30));
+        assert_eq!(results.width + preview.width, 120);
+
+        let (results, preview) = split_preview(Rect::new(0, 0, 80, 30));
+        assert_eq!((results.width, preview.width), (80, 80));
+        assert_eq!(preview.y, results.bottom());
+    }
+
+    #[test]
+    fn test_format_timestamps() {
+        assert_eq!(format_date(0), "1970-01-01 00:00");
+        assert_eq!(format_date(1_709_647_320), "2024-03-05 14:02");
+        assert_eq!(format_date(951_782_400), "2000-02-29 00:00");
+
+        let now = 1_709_647_320;
+        assert_eq!(format_age(now, now - 42), "42s");
+        assert_eq!(format_age(now, now - 3 * 60), "3m");
+        assert_eq!(format_age(now, now - 2 * 86_400 - 5), "2d");
+        assert_eq!(format_age(now, now - 5 * 604_800), "5w");
+        assert_eq!(format_age(now, now - 2 * 31_536_000), "2y");
+        assert_eq!(format_age(now, now + 10), "0s");
+
+        // Columns keep their width, even without a timestamp
+        assert_eq!(
+            timestamp_column(TimestampMode::Relative, Some(now - 3 * 60), now),
+            " 3m "
+        );
+        assert_eq!(timestamp_column(TimestampMode::Relative, None, now), "    ");
+        assert_eq!(
+            timestamp_column(TimestampMode::Absolute, Some(now), now),
+            "2024-03-05 "
+        );
+        for mode in [TimestampMode::Relative, TimestampMode::Absolute] {
+            assert_eq!(
+                timestamp_column(mode, Some(0), now).len(),
+                timestamp_column_width(mode)
+            );
+        }
+    }
+
+    #[test]
+    fn test_shows_original() {
+        let entry = |original: &str, full: &str| HistoryEntry {
+            original_line: original.to_string(),
+            full_command: full.to_string(),
+            ..Default::default()
+        };
+
+        assert!(!shows_original(&entry("", "ls -la")));
+        assert!(!shows_original(&entry("ls -la", "ls -la")));
+        // The zsh prefix only holds the times shown as "Last run" and "Duration"
+        assert!(!shows_original(&entry(": 1738093190:0;ls -la", "ls -la")));
+        assert!(!shows_original(&entry("#1738093190\nls -la", "ls -la")));
+        assert!(shows_original(&entry(": not a prefix;ls -la", "ls -la")));
+        // Fish's record shows how the command was escaped
+        assert!(shows_original(&entry(
+            "- cmd: echo a\\nb\n  when: 1738093190",
+            "echo a\nb"
+        )));
+        assert!(shows_original(&entry("ls  -la", "ls -la")));
+    }
+
+    #[test]
+    fn test_wrapped_highlights() {
+        let theme = Theme::default();
+        let spans = |line: &Line| -> Vec<(String, Style)> {
+            line.spans
+                .iter()
+                .map(|span| (span.content.to_string(), span.style))
+                .collect()
+        };
+        let highlights = [
+            // "get  p"
+            Highlight {
+                dimension: 0,
+                range: 7..13,
+            },
+            // "t  pods"
+            Highlight {
+                dimension: 1,
+                range: 9..16,
+            },
+        ];
+
+        // "café" ends in a multi-byte character and the spaces around the
+        // break are trimmed, so the second line starts later than it would
+        // if it followed right after the first
+        let lines = wrap_command("café  get  pods", &highlights, 6, &theme);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            spans(&lines[0]),
+            vec![("café".to_string(), Style::default())]
+        );
+        // The first dimension started first, so it keeps "get" where the
+        // second one overlaps it
+        assert_eq!(
+            spans(&lines[1]),
+            vec![("get".to_string(), theme.highlight(0))]
+        );
+        assert_eq!(
+            spans(&lines[2]),
+            vec![
+                ("p".to_string(), theme.highlight(0)),
+                ("ods".to_string(), theme.highlight(1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ordinal() {
+        let ordinals: Vec<String> = [0, 1, 2, 3, 10, 11, 12, 20, 21, 100]
+            .into_iter()
+            .map(ordinal)
+            .collect();
+        assert_eq!(
+            ordinals,
+            vec!["1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "101st"]
+        );
+    }
 }